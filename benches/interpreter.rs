@@ -0,0 +1,65 @@
+//! Criterion benchmarks for each phase: lexing and parsing a large file, and
+//! running the `.exl` workloads next to this file. Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use exline::interpreter::Interpreter;
+use exline::lexer::{Lexer, Token};
+use exline::parser::{Parser, Program};
+use std::hint::black_box;
+use std::io;
+
+const FIB: &str = include_str!("fib.exl");
+const METHODS: &str = include_str!("methods.exl");
+const LOOPS: &str = include_str!("loops.exl");
+
+/// Every workload repeated until the source is a few hundred KB. Only lexed
+/// and parsed, since running it would redefine the same names.
+fn large_source() -> String {
+    [FIB, METHODS, LOOPS].join("\n").repeat(200)
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    Lexer::new(source).tokenize().expect("benchmark source lexes")
+}
+
+fn parse(source: &str) -> Program {
+    Parser::new(tokenize(source)).parse().expect("benchmark source parses")
+}
+
+fn bench_lex(c: &mut Criterion) {
+    let source = large_source();
+    c.bench_function("lex large file", |b| b.iter(|| tokenize(black_box(&source))));
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let tokens = tokenize(&large_source());
+    c.bench_function("parse large file", |b| {
+        b.iter_batched(
+            || tokens.clone(),
+            |tokens| Parser::new(tokens).parse().expect("benchmark source parses"),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn bench_run(c: &mut Criterion) {
+    let mut group = c.benchmark_group("run");
+    // Each run takes tens of milliseconds, so fewer samples keep the total reasonable
+    group.sample_size(20);
+    for (name, source) in [("fib", FIB), ("methods", METHODS), ("loops", LOOPS)] {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || parse(source),
+                |program| {
+                    let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+                    interpreter.interpret(program).expect("benchmark program runs");
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lex, bench_parse, bench_run);
+criterion_main!(benches);
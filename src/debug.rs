@@ -20,13 +20,17 @@ fn debug_parsing(source: &str) {
                     println!("Parsed successfully!");
                     println!("AST: {:#?}", program);
                 }
-                Err(e) => {
-                    println!("Parse error: {}", e);
+                Err(errors) => {
+                    for e in errors {
+                        println!("Parse error: {}", e);
+                    }
                 }
             }
         }
-        Err(e) => {
-            println!("Tokenize error: {}", e);
+        Err(errors) => {
+            for e in errors {
+                println!("Tokenize error: {}", e);
+            }
         }
     }
     println!();
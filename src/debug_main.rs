@@ -24,8 +24,10 @@ fn main() {
                     println!("  {:?}", token);
                 }
             }
-            Err(e) => {
-                eprintln!("Lexer error: {}", e);
+            Err(errors) => {
+                for e in errors {
+                    eprintln!("Lexer error: {}", e);
+                }
             }
         }
         return;
@@ -0,0 +1,105 @@
+use std::fmt;
+
+const RED: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+/// What each line of a report starts with, naming the kind of error.
+const LABELS: [&str; 3] = ["Lexer error:", "Parser error:", "Runtime error:"];
+
+/// An error tied to a position in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExlineError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl ExlineError {
+    pub fn new(message: impl Into<String>, line: usize, column: usize) -> Self {
+        Self {
+            message: message.into(),
+            line,
+            column,
+        }
+    }
+
+    /// Renders the error followed by the offending source line and a caret
+    /// under the column it points at.
+    pub fn report(&self, source: &str) -> String {
+        let Some(line) = source.lines().nth(self.line.saturating_sub(1)) else {
+            return self.to_string();
+        };
+
+        // Keep tabs so the caret lines up with the source as displayed
+        let padding: String = line
+            .chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        format!("{}\n  {}\n  {}^", self, line, padding)
+    }
+}
+
+/// Wraps text in the escape codes that show it in bold red on a terminal.
+pub fn red(text: &str) -> String {
+    format!("{}{}{}", RED, text, RESET)
+}
+
+/// Colors an error report for a terminal: the label at the start of each
+/// error and the caret under the offending column are shown in red, and
+/// everything else, including the quoted source line, is left as it is.
+pub fn colorize(report: &str) -> String {
+    report
+        .lines()
+        .map(|line| {
+            if line.trim() == "^" {
+                return line.replace('^', &red("^"));
+            }
+            match LABELS.iter().find(|label| line.starts_with(*label)) {
+                Some(label) => format!("{}{}", red(label), &line[label.len()..]),
+                None => line.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl fmt::Display for ExlineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_points_at_column() {
+        let error = ExlineError::new("Unexpected character: @", 2, 9);
+        assert_eq!(
+            error.report("Int a = 1\nInt b = @\n"),
+            "Unexpected character: @ at line 2, column 9\n  Int b = @\n          ^"
+        );
+        assert_eq!(error.report(""), "Unexpected character: @ at line 2, column 9");
+
+        // Columns count characters, so multi-byte characters take one column each
+        let error = ExlineError::new("Unexpected character: @", 1, 13);
+        assert_eq!(
+            error.report("String é = \"😀\" @"),
+            "Unexpected character: @ at line 1, column 13\n  String é = \"😀\" @\n              ^"
+        );
+    }
+
+    #[test]
+    fn test_colorize() {
+        let report = "Parser error: Expected expression at line 1, column 9\n  Int a = )\n          ^\nRuntime error: boom";
+        assert_eq!(
+            colorize(report),
+            "\x1b[1;31mParser error:\x1b[0m Expected expression at line 1, column 9\n  \
+             Int a = )\n          \x1b[1;31m^\x1b[0m\n\x1b[1;31mRuntime error:\x1b[0m boom"
+        );
+        assert_eq!(colorize("Unknown command: :x"), "Unknown command: :x");
+    }
+}
@@ -0,0 +1,580 @@
+use crate::parser::{
+    Associativity, BinaryOperator, ClassField, Expression, InterfaceMethod, KeywordArgument, Method, Parameter, Program,
+    Statement, StringPart, Type, UnaryOperator, NOT_PRECEDENCE,
+};
+
+const INDENT: &str = "  ";
+
+/// Re-emits a parsed program as canonically formatted Exline source.
+pub fn format(program: &Program) -> String {
+    let mut output = String::new();
+
+    for (i, statement) in program.statements.iter().enumerate() {
+        // Keep definitions visually separated from surrounding code
+        if i > 0 && (is_definition(statement) || is_definition(&program.statements[i - 1])) {
+            output.push('\n');
+        }
+        format_statement(statement, 0, &mut output);
+    }
+
+    output
+}
+
+fn is_definition(statement: &Statement) -> bool {
+    matches!(
+        statement,
+        Statement::FunctionDefinition { .. }
+            | Statement::ClassDefinition { .. }
+            | Statement::InterfaceDefinition { .. }
+    )
+}
+
+fn push_line(output: &mut String, depth: usize, line: &str) {
+    for _ in 0..depth {
+        output.push_str(INDENT);
+    }
+    output.push_str(line);
+    output.push('\n');
+}
+
+fn format_block(statements: &[Statement], depth: usize, output: &mut String) {
+    for statement in statements {
+        format_statement(statement, depth, output);
+    }
+}
+
+/// The first line of a statement as `--fmt` would print it, used to label
+/// statements in `--trace` output.
+pub(crate) fn format_statement_line(statement: &Statement) -> String {
+    let mut output = String::new();
+    format_statement(statement, 0, &mut output);
+    output.lines().next().unwrap_or_default().to_string()
+}
+
+/// Writes the branches of an `if`. An else branch holding nothing but
+/// another `if` is written as an `elif`, so chains stay flat.
+fn format_if_branches(then_branch: &[Statement], else_branch: Option<&[Statement]>, depth: usize, output: &mut String) {
+    format_block(then_branch, depth + 1, output);
+    match else_branch {
+        Some([Statement::If { condition, then_branch, else_branch, .. }]) => {
+            push_line(output, depth, &format!("elif {}", format_expression(condition)));
+            format_if_branches(then_branch, else_branch.as_deref(), depth, output);
+        }
+        Some(else_statements) => {
+            push_line(output, depth, "else");
+            format_block(else_statements, depth + 1, output);
+        }
+        None => {}
+    }
+}
+
+fn format_statement(statement: &Statement, depth: usize, output: &mut String) {
+    match statement {
+        Statement::VariableDeclaration { name, var_type, value, .. } => {
+            let line = format!("{} {} = {}", format_type(var_type), name, format_expression(value));
+            push_line(output, depth, &line);
+        }
+
+        Statement::ConstDeclaration { name, var_type, value, .. } => {
+            let line = format!("const {} {} = {}", format_type(var_type), name, format_expression(value));
+            push_line(output, depth, &line);
+        }
+
+        Statement::MultipleDeclaration { declarations, .. } => {
+            // Re-join the declarations as `Int a = 1, b = 2`
+            let mut line = String::new();
+            for (i, declaration) in declarations.iter().enumerate() {
+                let mut formatted = String::new();
+                format_statement(declaration, 0, &mut formatted);
+                let formatted = formatted.trim_end();
+                if i == 0 {
+                    line.push_str(formatted);
+                } else if let Statement::VariableDeclaration { name, value, .. }
+                | Statement::ConstDeclaration { name, value, .. } = declaration
+                {
+                    line.push_str(&format!(", {} = {}", name, format_expression(value)));
+                }
+            }
+            push_line(output, depth, &line);
+        }
+
+        Statement::FunctionDefinition { name, parameters, return_type, body, .. } => {
+            let line = format!(
+                "def {}({}) -> {}",
+                name,
+                format_parameters(parameters),
+                format_type(return_type)
+            );
+            push_line(output, depth, &line);
+            format_block(body, depth + 1, output);
+            push_line(output, depth, "end");
+        }
+
+        Statement::If { condition, then_branch, else_branch, .. } => {
+            push_line(output, depth, &format!("if {}", format_expression(condition)));
+            format_if_branches(then_branch, else_branch.as_deref(), depth, output);
+            push_line(output, depth, "end");
+        }
+
+        Statement::While { condition, body, .. } => {
+            push_line(output, depth, &format!("while {}", format_expression(condition)));
+            format_block(body, depth + 1, output);
+            push_line(output, depth, "end");
+        }
+
+        Statement::RepeatUntil { body, condition, .. } => {
+            push_line(output, depth, "repeat");
+            format_block(body, depth + 1, output);
+            push_line(output, depth, &format!("until {}", format_expression(condition)));
+        }
+
+        Statement::Block { body, .. } => {
+            push_line(output, depth, "do");
+            format_block(body, depth + 1, output);
+            push_line(output, depth, "end");
+        }
+
+        Statement::Match { scrutinee, cases, default, .. } => {
+            push_line(output, depth, &format!("match {}", format_expression(scrutinee)));
+            for case in cases {
+                push_line(output, depth + 1, &format!("case {}", format_expression(&case.value)));
+                format_block(&case.body, depth + 2, output);
+            }
+            if let Some(default_statements) = default {
+                push_line(output, depth + 1, "default");
+                format_block(default_statements, depth + 2, output);
+            }
+            push_line(output, depth, "end");
+        }
+
+        Statement::Try { body, catch_var, catch_body, .. } => {
+            push_line(output, depth, "try");
+            format_block(body, depth + 1, output);
+            push_line(output, depth, &format!("catch {}", catch_var));
+            format_block(catch_body, depth + 1, output);
+            push_line(output, depth, "end");
+        }
+
+        Statement::Raise { message, .. } => {
+            push_line(output, depth, &format!("raise {}", format_expression(message)));
+        }
+
+        Statement::Return { value, .. } => match value {
+            Some(value) => push_line(output, depth, &format!("return {}", format_expression(value))),
+            None => push_line(output, depth, "return"),
+        },
+
+        Statement::Break(_) => push_line(output, depth, "break"),
+
+        Statement::Continue(_) => push_line(output, depth, "continue"),
+
+        Statement::ClassDefinition { name, implements, fields, methods, .. } => {
+            let line = if implements.is_empty() {
+                format!("class {}", name)
+            } else {
+                format!("class {} implements {}", name, implements.join(", "))
+            };
+            push_line(output, depth, &line);
+
+            for field in fields {
+                format_field(field, depth + 1, output);
+            }
+            for (i, method) in methods.iter().enumerate() {
+                if i > 0 || !fields.is_empty() {
+                    output.push('\n');
+                }
+                format_method(method, depth + 1, output);
+            }
+
+            push_line(output, depth, "end");
+        }
+
+        Statement::InterfaceDefinition { name, methods, .. } => {
+            push_line(output, depth, &format!("interface {}", name));
+            for method in methods {
+                format_interface_method(method, depth + 1, output);
+            }
+            push_line(output, depth, "end");
+        }
+
+        Statement::Module { name, body, .. } => {
+            push_line(output, depth, &format!("module {}", name));
+            format_block(body, depth + 1, output);
+            push_line(output, depth, "end");
+        }
+
+        Statement::Assignment { target, value, .. } => {
+            let line = format!("{} = {}", format_expression(target), format_expression(value));
+            push_line(output, depth, &line);
+        }
+
+        Statement::Expression(expr, _) => {
+            push_line(output, depth, &format_expression(expr));
+        }
+    }
+}
+
+fn format_field(field: &ClassField, depth: usize, output: &mut String) {
+    let line = match &field.default {
+        Some(default) => format!("{} {} = {}", format_type(&field.field_type), field.name, format_expression(default)),
+        None => format!("{} {}", format_type(&field.field_type), field.name),
+    };
+    push_line(output, depth, &line);
+}
+
+fn format_method(method: &Method, depth: usize, output: &mut String) {
+    let line = format!(
+        "{}def {}({}) : {}",
+        if method.is_static { "static " } else { "" },
+        method.name,
+        format_parameters(&method.parameters),
+        format_type(&method.return_type)
+    );
+    push_line(output, depth, &line);
+    format_block(&method.body, depth + 1, output);
+    push_line(output, depth, "end");
+}
+
+fn format_interface_method(method: &InterfaceMethod, depth: usize, output: &mut String) {
+    let line = format!(
+        "def {}({}) : {}",
+        method.name,
+        format_parameters(&method.parameters),
+        format_type(&method.return_type)
+    );
+    push_line(output, depth, &line);
+    if let Some(body) = &method.body {
+        format_block(body, depth + 1, output);
+        push_line(output, depth, "end");
+    }
+}
+
+pub(crate) fn format_parameters(parameters: &[Parameter]) -> String {
+    parameters
+        .iter()
+        .map(|param| match &param.default {
+            Some(default) => format!(
+                "{}: {} = {}",
+                param.name,
+                format_type(&param.param_type),
+                format_expression(default)
+            ),
+            None if param.variadic => format!("{}: {}...", param.name, format_type(&param.param_type)),
+            None => format!("{}: {}", param.name, format_type(&param.param_type)),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub(crate) fn format_type(type_: &Type) -> String {
+    match type_ {
+        Type::Int => "Int".to_string(),
+        Type::Float => "Float".to_string(),
+        Type::String => "String".to_string(),
+        Type::Char => "Char".to_string(),
+        Type::Map => "Map".to_string(),
+        Type::List => "List".to_string(),
+        Type::Function => "Function".to_string(),
+        Type::MapOf(value_type) => format!("Map<{}>", format_type(value_type)),
+        Type::ListOf(element_type) => format!("List<{}>", format_type(element_type)),
+        Type::Void => "void".to_string(),
+        Type::Custom(name) => name.clone(),
+    }
+}
+
+fn format_arguments(arguments: &[Expression], keyword_arguments: &[KeywordArgument]) -> String {
+    arguments
+        .iter()
+        .map(format_expression)
+        .chain(
+            keyword_arguments
+                .iter()
+                .map(|argument| format!("{}: {}", argument.name, format_expression(&argument.value))),
+        )
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn operator_symbol(operator: &BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Subtract => "-",
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::Divide => "/",
+        BinaryOperator::IntDivide => "//",
+        BinaryOperator::Modulo => "%",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+        BinaryOperator::And => "and",
+        BinaryOperator::Or => "or",
+        BinaryOperator::Less => "<",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::LessEqual => "<=",
+        BinaryOperator::GreaterEqual => ">=",
+        BinaryOperator::BitAnd => "&",
+        BinaryOperator::BitOr => "|",
+        BinaryOperator::BitXor => "^",
+        BinaryOperator::ShiftLeft => "<<",
+        BinaryOperator::ShiftRight => ">>",
+        BinaryOperator::Power => "**",
+    }
+}
+
+/// Formats an operand, parenthesizing it when it binds looser than its parent.
+/// Most operators are left-associative, so the right operand also needs
+/// parentheses at equal precedence; `**` is right-associative, so there it's
+/// the left operand, which also binds tighter than a unary minus.
+fn format_operand(expr: &Expression, parent: &BinaryOperator, is_right: bool) -> String {
+    let formatted = format_expression(expr);
+    let right_associative = parent.associativity() == Associativity::Right;
+    match expr {
+        Expression::Binary { operator, .. } => {
+            let (child, parent) = (operator.precedence(), parent.precedence());
+            if child < parent || (child == parent && is_right != right_associative) {
+                return format!("({})", formatted);
+            }
+        }
+        Expression::Unary { .. } if right_associative && !is_right => {
+            return format!("({})", formatted);
+        }
+        // `not` binds looser than most binary operators
+        Expression::Unary { operator: UnaryOperator::Not, .. } if parent.precedence() > NOT_PRECEDENCE => {
+            return format!("({})", formatted);
+        }
+        _ => {}
+    }
+    formatted
+}
+
+/// Formats the receiver of a postfix operation (`.`, `[]`).
+fn format_receiver(expr: &Expression) -> String {
+    match expr {
+        Expression::Binary { .. } | Expression::Unary { .. } => format!("({})", format_expression(expr)),
+        _ => format_expression(expr),
+    }
+}
+
+/// Chars are re-escaped so the literal lexes back to the same character.
+fn format_char(c: char) -> String {
+    match c {
+        '\n' => "'\\n'".to_string(),
+        '\t' => "'\\t'".to_string(),
+        '\r' => "'\\r'".to_string(),
+        '\0' => "'\\0'".to_string(),
+        '\\' => "'\\\\'".to_string(),
+        '\'' => "'\\''".to_string(),
+        _ => format!("'{}'", c),
+    }
+}
+
+/// Floats always keep a decimal point so they re-lex as float literals.
+fn format_float(n: f64) -> String {
+    let formatted = n.to_string();
+    if formatted.contains('.') {
+        formatted
+    } else {
+        format!("{}.0", formatted)
+    }
+}
+
+fn format_expression(expr: &Expression) -> String {
+    match expr {
+        Expression::Integer(n) => n.to_string(),
+        Expression::Float(n) => format_float(*n),
+        Expression::Char(c) => format_char(*c),
+        Expression::String(s) => format!("\"{}\"", s),
+        Expression::Map(entries) => {
+            if entries.is_empty() {
+                return "{}".to_string();
+            }
+            let entries = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", format_expression(key), format_expression(value)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{{ {} }}", entries)
+        }
+        Expression::List(elements) => format!("[{}]", format_arguments(elements, &[])),
+        Expression::Identifier(name) => name.clone(),
+        Expression::Binary { left, operator, right } => format!(
+            "{} {} {}",
+            format_operand(left, operator, false),
+            operator_symbol(operator),
+            format_operand(right, operator, true)
+        ),
+        Expression::Unary { operator: UnaryOperator::Negate, operand } => match **operand {
+            Expression::Binary { .. } | Expression::Unary { operator: UnaryOperator::Not, .. } => {
+                format!("-({})", format_expression(operand))
+            }
+            _ => format!("-{}", format_expression(operand)),
+        },
+        Expression::Unary { operator: UnaryOperator::Not, operand } => match &**operand {
+            Expression::Binary { operator, .. } if operator.precedence() < NOT_PRECEDENCE => {
+                format!("not ({})", format_expression(operand))
+            }
+            _ => format!("not {}", format_expression(operand)),
+        },
+        Expression::FunctionCall { name, arguments, keyword_arguments } => {
+            format!("{}({})", name, format_arguments(arguments, keyword_arguments))
+        }
+        Expression::MethodCall { object, method, arguments, keyword_arguments, optional } => {
+            format!(
+                "{}{}{}({})",
+                format_receiver(object),
+                if *optional { "?." } else { "." },
+                method,
+                format_arguments(arguments, keyword_arguments)
+            )
+        }
+        Expression::FieldAccess { object, field, optional } => {
+            format!("{}{}{}", format_receiver(object), if *optional { "?." } else { "." }, field)
+        }
+        Expression::Index { object, index } => {
+            format!("{}[{}]", format_receiver(object), format_expression(index))
+        }
+        Expression::ObjectCreation { class_name } => format!("{}.new()", class_name),
+        Expression::StringInterpolation { parts } => {
+            let mut result = String::from("\"");
+            for part in parts {
+                match part {
+                    StringPart::Literal(text) => result.push_str(text),
+                    StringPart::Expression(expr) => {
+                        result.push_str("#{");
+                        result.push_str(&format_expression(expr));
+                        result.push('}');
+                    }
+                }
+            }
+            result.push('"');
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::{without_spans, Parser};
+
+    fn parse(source: &str) -> Program {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        parser.parse().unwrap()
+    }
+
+    #[test]
+    fn test_format_normalizes_spacing_and_indentation() {
+        let source = "def add(a: Int,b: Int) -> Int\n    a+b*2\nend\nprint(add(1,2) / 2.0)";
+        let formatted = format(&parse(source));
+
+        assert_eq!(
+            formatted,
+            "def add(a: Int, b: Int) -> Int\n  a + b * 2\nend\n\nprint(add(1, 2) / 2.0)\n"
+        );
+    }
+
+    #[test]
+    fn test_format_writes_elif() {
+        let source = "if a\n  print(1)\nelse\n  if b\n    print(2)\n  else\n    print(3)\n  end\nend";
+        assert_eq!(
+            format(&parse(source)),
+            "if a\n  print(1)\nelif b\n  print(2)\nelse\n  print(3)\nend\n"
+        );
+    }
+
+    #[test]
+    fn test_format_empty_program() {
+        let tokens = Lexer::new("\n# only comments\n\n").tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(format(&program), "");
+    }
+
+    #[test]
+    fn test_format_round_trips() {
+        let source = r#"
+interface Greetable
+    def greet(greeting: String = "Hi") : String
+    def describe() : String
+        "a greeter"
+    end
+end
+class Person implements Greetable
+    String name
+    Map tags
+    Int visits = 1 + 2
+    def rename(name: String) : Person
+        this.name = name
+        return this
+    end
+    static def named(name: String) : Person
+        Person.new().rename(name)
+    end
+    def greet() : void
+        if this.name == "Bob"
+            return
+        end
+        if this.name == "Bob"
+            print("Hi #{name}")
+        elif this.name == "Al"
+        elif this.name == "Cy"
+            print("Cy")
+        else
+            print((1 + 2) * 3 - (4 - 5), -(1 + 2), -name[-1], (1 | 2) & 3 << 1 + 1, (2 ** 3) ** 2 ** -1, (-2) ** 2, 7 // 2 % 3, "a" < "b" == (1 >= 2), 1 <= 2 > 0 | 1)
+            print(not 1 and 2 or not (0 or 1), (not 1) == 0, -(not 0), not -1 != 2, (-1).abs)
+        end
+    end
+end
+Person p = Person.new()
+module Geo
+    class Point implements Greetable, Named
+    end
+    def origin() -> Geo.Point
+        Geo.Point.new()
+    end
+end
+Geo.Point origin = Geo.origin()
+def total(label: String, nums: Int...) -> Int
+    len(nums)
+end
+print(total("a", 1, 2), total(label: "b"), p.rename(name: "Al").greet(), Person.named("Cy"), p?.name, p?.rename("Di")?.name)
+const Float RATE = 1.5, TAX = 0.2
+List chars = ['a', '\n', '\'', '\\']
+List<Int> empty = []
+Map<List<List<Int>>> nested = {}
+Int a = 1, b = 2
+Map m = { "a": 1, "b": 2 }
+List xs = [1, [2, 3], []]
+m["a"] = p.name
+while m["a"] == "x"
+    if 1
+        break
+    end
+    continue
+end
+repeat
+    m["a"] = "y"
+until m["a"] != "x"
+do
+    Int local = 1
+end
+try
+    raise "missing"
+catch e
+    print(e)
+end
+match m["a"]
+    case "x"
+        print(1)
+    default
+        print(2)
+end
+"#;
+        let program = parse(source);
+        let formatted = format(&program);
+
+        assert_eq!(without_spans(&parse(&formatted)), without_spans(&program));
+        assert_eq!(format(&parse(&formatted)), formatted);
+    }
+}
@@ -1,49 +1,160 @@
-use crate::parser::{Program, Statement, Expression, BinaryOperator, Type, Parameter, ClassField, Method};
-use std::collections::HashMap;
+use crate::formatter;
+use crate::parser::{Program, Statement, Expression, BinaryOperator, UnaryOperator, Type, Parameter, KeywordArgument, ClassField, Method, Span};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+use std::num::IntErrorKind;
+use std::rc::Rc;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Integer(i64),
+    Float(f64),
     String(String),
+    Char(char),
+    Map(HashMap<String, Value>),
+    List(Vec<Value>),
     Function {
+        /// The name the function was defined with, used to call built-ins
+        /// through a function value and to show it.
+        name: String,
         parameters: Vec<Parameter>,
         return_type: Type,
-        body: Vec<Statement>,
+        /// Shared so calling a function doesn't copy its body.
+        body: Rc<[Statement]>,
     },
+    /// Objects are shared: copying the value, as assignment and passing an
+    /// argument do, gives another reference to the same fields.
     Object {
         class_name: String,
-        fields: HashMap<String, Value>,
+        fields: Rc<RefCell<HashMap<String, Value>>>,
     },
     Void,
 }
 
 impl Value {
-    pub fn type_name(&self) -> &'static str {
+    /// Name of the value's type as written in Exline source; objects report
+    /// their class name.
+    pub fn type_name(&self) -> String {
         match self {
-            Value::Integer(_) => "Int",
-            Value::String(_) => "String",
-            Value::Function { .. } => "Function",
-            Value::Object { class_name, .. } => "Object",
-            Value::Void => "Void",
+            Value::Integer(_) => "Int".to_string(),
+            Value::Float(_) => "Float".to_string(),
+            Value::String(_) => "String".to_string(),
+            Value::Char(_) => "Char".to_string(),
+            Value::Map(_) => "Map".to_string(),
+            Value::List(_) => "List".to_string(),
+            Value::Function { .. } => "Function".to_string(),
+            Value::Object { class_name, .. } => class_name.clone(),
+            Value::Void => "Void".to_string(),
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
+/// Shows a value the way `print` does. Strings and Chars show bare at the
+/// top level but quoted inside a List or Map, so `["a, b"]` can't be mistaken
+/// for two elements. An object shows only its class, never its fields, so
+/// a cycle through objects can't make this recurse forever; Lists and Maps
+/// are copied on assignment and can't contain themselves.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Value::Integer(n) => n.to_string(),
-            Value::String(s) => s.clone(),
-            Value::Function { .. } => "<function>".to_string(),
-            Value::Object { class_name, .. } => format!("<{} object>", class_name),
-            Value::Void => "void".to_string(),
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{}", format_float(*n)),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Map(entries) => {
+                // Sort keys so printing a map is deterministic
+                let mut keys: Vec<&String> = entries.keys().collect();
+                keys.sort();
+                write!(f, "{{")?;
+                for (i, key) in keys.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}: ", key)?;
+                    write_element(f, &entries[*key])?;
+                }
+                write!(f, "}}")
+            }
+            Value::List(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_element(f, element)?;
+                }
+                write!(f, "]")
+            }
+            Value::Function { name, .. } => write!(f, "<function {}>", name),
+            Value::Object { class_name, .. } => write!(f, "<{} object>", class_name),
+            Value::Void => write!(f, "void"),
         }
     }
 }
 
-pub struct Environment {
+/// Writes a List element or Map value, quoting Strings and Chars.
+fn write_element(f: &mut fmt::Formatter, value: &Value) -> fmt::Result {
+    match value {
+        Value::String(s) => write!(f, "{:?}", s),
+        Value::Char(c) => write!(f, "{:?}", c),
+        other => write!(f, "{}", other),
+    }
+}
+
+/// How many arguments a function with these parameters takes, as in `2`,
+/// `1 to 3` or `at least 1`.
+fn describe_arity(parameters: &[Parameter]) -> String {
+    let required = parameters.iter().filter(|p| p.default.is_none() && !p.variadic).count();
+    if parameters.last().is_some_and(|p| p.variadic) {
+        format!("at least {}", required)
+    } else if required == parameters.len() {
+        required.to_string()
+    } else {
+        format!("{} to {}", required, parameters.len())
+    }
+}
+
+/// How a statement hands control back to the enclosing block. `Return` ends
+/// the enclosing function with that result; `Break` and `Continue` unwind to
+/// the nearest loop. `Value` is a non-Void expression statement's value: it
+/// ends a function body, and is the result of a block it ends, but a loop
+/// body discards it.
+enum Flow {
+    Value(Value),
+    Return(Value),
+    Break,
+    Continue,
+}
+
+/// Variables declared in one block. Constants are tracked per scope so a
+/// block-local constant's name is free again once the block ends, and so are
+/// the `List<T>` and `Map<T>` types variables were declared with, which every
+/// later assignment must still match.
+#[derive(Debug, Clone, Default)]
+struct Scope {
     variables: HashMap<String, Value>,
+    constants: HashSet<String>,
+    collection_types: HashMap<String, Type>,
+}
+
+pub struct Environment {
+    /// Innermost scope last; the first is the global scope.
+    scopes: Vec<Scope>,
+    /// Index into `scopes` where each active call's frame begins. Code in a
+    /// call can read every scope, but its writes to variables declared
+    /// outside its frame stay in the frame and are dropped when it returns.
+    frames: Vec<usize>,
     functions: HashMap<String, Value>,
-    classes: HashMap<String, ClassDefinition>,
+    /// Names of the functions provided by the interpreter itself.
+    builtins: HashSet<String>,
+    /// Shared so method dispatch and object creation don't copy the class.
+    classes: HashMap<String, Rc<ClassDefinition>>,
     interfaces: HashMap<String, InterfaceDefinition>,
+    /// Qualified names of the modules defined so far, such as `Geo.Shapes`.
+    modules: HashSet<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -51,7 +162,7 @@ pub struct ClassDefinition {
     pub name: String,
     pub fields: Vec<ClassField>,
     pub methods: Vec<Method>,
-    pub implements: Option<String>,
+    pub implements: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -63,31 +174,344 @@ pub struct InterfaceDefinition {
 impl Environment {
     pub fn new() -> Self {
         let mut env = Self {
-            variables: HashMap::new(),
+            scopes: vec![Scope::default()],
+            frames: Vec::new(),
             functions: HashMap::new(),
+            builtins: HashSet::new(),
             classes: HashMap::new(),
             interfaces: HashMap::new(),
+            modules: HashSet::new(),
         };
 
         // Add built-in print function
         env.functions.insert("print".to_string(), Value::Function {
+            name: "print".to_string(),
+            parameters: vec![Parameter {
+                name: "values".to_string(),
+                param_type: Type::String, // For simplicity, print takes any number of any values
+                default: None,
+                variadic: true,
+            }],
+            return_type: Type::Void,
+            body: Rc::new([]), // Built-in functions have empty body
+        });
+
+        // Add built-in eprint and eprintln functions: print to stderr, without
+        // and with a trailing newline
+        for name in ["eprint", "eprintln"] {
+            env.functions.insert(name.to_string(), Value::Function {
+                name: name.to_string(),
+                parameters: vec![Parameter {
+                    name: "values".to_string(),
+                    param_type: Type::String, // Like print, takes any number of any values
+                    default: None,
+                    variadic: true,
+                }],
+                return_type: Type::Void,
+                body: Rc::new([]),
+            });
+        }
+
+        // Add built-in write function
+        env.functions.insert("write".to_string(), Value::Function {
+            name: "write".to_string(),
+            parameters: vec![Parameter {
+                name: "value".to_string(),
+                param_type: Type::String, // Like print, write takes any value
+                default: None,
+                variadic: false,
+            }],
+            return_type: Type::Void,
+            body: Rc::new([]),
+        });
+
+        // Add built-in intdiv function
+        env.functions.insert("intdiv".to_string(), Value::Function {
+            name: "intdiv".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "a".to_string(),
+                    param_type: Type::Int,
+                    default: None,
+                    variadic: false,
+                },
+                Parameter {
+                    name: "b".to_string(),
+                    param_type: Type::Int,
+                    default: None,
+                    variadic: false,
+                },
+            ],
+            return_type: Type::Int,
+            body: Rc::new([]),
+        });
+
+        // Add built-in len function
+        env.functions.insert("len".to_string(), Value::Function {
+            name: "len".to_string(),
             parameters: vec![Parameter {
                 name: "value".to_string(),
-                param_type: Type::String, // For simplicity, print takes any value
+                param_type: Type::Map, // Also accepts List and String
+                default: None,
+                variadic: false,
+            }],
+            return_type: Type::Int,
+            body: Rc::new([]),
+        });
+
+        // Add built-in type function
+        env.functions.insert("type".to_string(), Value::Function {
+            name: "type".to_string(),
+            parameters: vec![Parameter {
+                name: "value".to_string(),
+                param_type: Type::String, // Takes any value
+                default: None,
+                variadic: false,
             }],
             return_type: Type::String,
-            body: vec![], // Built-in functions have empty body
+            body: Rc::new([]),
+        });
+
+        // Add built-in exit function, which stops the program with an exit code
+        env.functions.insert("exit".to_string(), Value::Function {
+            name: "exit".to_string(),
+            parameters: vec![Parameter {
+                name: "code".to_string(),
+                param_type: Type::Int,
+                default: None,
+                variadic: false,
+            }],
+            return_type: Type::Void,
+            body: Rc::new([]),
+        });
+
+        // Add built-in clone function, a deep copy that shares nothing with the original
+        env.functions.insert("clone".to_string(), Value::Function {
+            name: "clone".to_string(),
+            parameters: vec![Parameter {
+                name: "value".to_string(),
+                param_type: Type::String, // Takes any value
+                default: None,
+                variadic: false,
+            }],
+            return_type: Type::Void, // Returns a value of the argument's type
+            body: Rc::new([]),
+        });
+
+        // Add built-in type predicates, returning 1 or 0
+        for predicate in ["isInt", "isString", "isObject"] {
+            env.functions.insert(predicate.to_string(), Value::Function {
+                name: predicate.to_string(),
+                parameters: vec![Parameter {
+                    name: "value".to_string(),
+                    param_type: Type::String, // Takes any value
+                    default: None,
+                    variadic: false,
+                }],
+                return_type: Type::Int,
+                body: Rc::new([]),
+            });
+        }
+
+        // Add built-in rounding functions, from a number to an Int
+        for rounding in ["floor", "ceil", "round"] {
+            env.functions.insert(rounding.to_string(), Value::Function {
+                name: rounding.to_string(),
+                parameters: vec![Parameter {
+                    name: "x".to_string(),
+                    param_type: Type::Float, // Also accepts Int
+                    default: None,
+                    variadic: false,
+                }],
+                return_type: Type::Int,
+                body: Rc::new([]),
+            });
+        }
+
+        // Add built-in toInt function, parsing a String such as " +42 "
+        env.functions.insert("toInt".to_string(), Value::Function {
+            name: "toInt".to_string(),
+            parameters: vec![Parameter {
+                name: "value".to_string(),
+                param_type: Type::String, // Also accepts Int
+                default: None,
+                variadic: false,
+            }],
+            return_type: Type::Int,
+            body: Rc::new([]),
+        });
+
+        // Add built-in range function: range(end) or range(start, end)
+        env.functions.insert("range".to_string(), Value::Function {
+            name: "range".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "start".to_string(),
+                    param_type: Type::Int,
+                    default: None,
+                    variadic: false,
+                },
+                Parameter {
+                    name: "end".to_string(),
+                    param_type: Type::Int,
+                    default: None,
+                    variadic: false,
+                },
+            ],
+            return_type: Type::List,
+            body: Rc::new([]),
+        });
+
+        // Add built-in map, filter and reduce, which take a List and a function
+        for (name, return_type) in [("map", Type::List), ("filter", Type::List), ("reduce", Type::Int)] {
+            let mut parameters = vec![
+                Parameter {
+                    name: "list".to_string(),
+                    param_type: Type::List,
+                    default: None,
+                    variadic: false,
+                },
+                Parameter {
+                    name: "function".to_string(),
+                    param_type: Type::Function,
+                    default: None,
+                    variadic: false,
+                },
+            ];
+            if name == "reduce" {
+                parameters.push(Parameter {
+                    name: "initial".to_string(),
+                    param_type: Type::Int, // Any value, like the result
+                    default: None,
+                    variadic: false,
+                });
+            }
+            env.functions.insert(name.to_string(), Value::Function {
+                name: name.to_string(),
+                parameters,
+                return_type,
+                body: Rc::new([]),
+            });
+        }
+
+        // Add built-in assert function: assert(condition) or assert(condition, message)
+        env.functions.insert("assert".to_string(), Value::Function {
+            name: "assert".to_string(),
+            parameters: vec![
+                Parameter {
+                    name: "condition".to_string(),
+                    param_type: Type::Int, // Any value, tested for truthiness
+                    default: None,
+                    variadic: false,
+                },
+                Parameter {
+                    name: "message".to_string(),
+                    param_type: Type::String,
+                    default: Some(Expression::String("".to_string())),
+                    variadic: false,
+                },
+            ],
+            return_type: Type::Void,
+            body: Rc::new([]),
         });
 
+        env.builtins = env.functions.keys().cloned().collect();
         env
     }
 
+    fn current_scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("the global scope is never popped")
+    }
+
+    /// The scope that declares `name`, innermost first.
+    fn declaring_scope(&self, name: &str) -> Option<&Scope> {
+        self.scopes.iter().rev().find(|scope| scope.variables.contains_key(name))
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    /// Starts a call frame with one empty scope for the parameters.
+    fn push_frame(&mut self) {
+        self.frames.push(self.scopes.len());
+        self.push_scope();
+    }
+
+    /// How many calls are currently running.
+    pub fn call_depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Drops the current call frame along with any block scopes left in it.
+    fn pop_frame(&mut self) {
+        if let Some(start) = self.frames.pop() {
+            self.scopes.truncate(start);
+        }
+    }
+
     pub fn define_variable(&mut self, name: String, value: Value) {
-        self.variables.insert(name, value);
+        let scope = self.current_scope();
+        scope.collection_types.remove(&name);
+        scope.variables.insert(name, value);
+    }
+
+    /// Declares a variable with a `List<T>` or `Map<T>` type, which is kept
+    /// so assignments to the variable, or to its elements, can be checked.
+    pub fn define_typed_variable(&mut self, name: String, value: Value, var_type: Type) {
+        let scope = self.current_scope();
+        scope.collection_types.insert(name.clone(), var_type);
+        scope.variables.insert(name, value);
+    }
+
+    /// The `List<T>` or `Map<T>` type the variable `name` was declared with,
+    /// looking through enclosing scopes the same way a read would.
+    pub fn collection_type(&self, name: &str) -> Option<&Type> {
+        self.declaring_scope(name).and_then(|scope| scope.collection_types.get(name))
+    }
+
+    /// Updates the variable in the innermost scope that declares it, or
+    /// declares it in the current scope if none does. A call only updates
+    /// its own copy of a variable declared outside it, kept in the call's
+    /// frame, so the caller still sees the old value once the call returns.
+    pub fn assign_variable(&mut self, name: String, value: Value) {
+        let frame_start = self.frames.last().copied().unwrap_or(0);
+        match self.scopes.iter().rposition(|scope| scope.variables.contains_key(&name)) {
+            Some(index) if index >= frame_start => {
+                self.scopes[index].variables.insert(name, value);
+            }
+            Some(index) => {
+                let collection_type = self.scopes[index].collection_types.get(&name).cloned();
+                let frame_scope = &mut self.scopes[frame_start];
+                if let Some(collection_type) = collection_type {
+                    frame_scope.collection_types.insert(name.clone(), collection_type);
+                }
+                frame_scope.variables.insert(name, value);
+            }
+            None => self.define_variable(name, value),
+        }
     }
 
     pub fn get_variable(&self, name: &str) -> Option<&Value> {
-        self.variables.get(name)
+        self.declaring_scope(name).and_then(|scope| scope.variables.get(name))
+    }
+
+    pub fn define_constant(&mut self, name: String, value: Value) {
+        let scope = self.current_scope();
+        scope.constants.insert(name.clone());
+        scope.variables.insert(name, value);
+    }
+
+    /// Whether `name` refers to a constant, looking through enclosing scopes
+    /// the same way a read would.
+    pub fn is_constant(&self, name: &str) -> bool {
+        self.declaring_scope(name).is_some_and(|scope| scope.constants.contains(name))
     }
 
     pub fn define_function(&mut self, name: String, value: Value) {
@@ -98,11 +522,15 @@ impl Environment {
         self.functions.get(name)
     }
 
+    pub fn is_builtin(&self, name: &str) -> bool {
+        self.builtins.contains(name)
+    }
+
     pub fn define_class(&mut self, name: String, class_def: ClassDefinition) {
-        self.classes.insert(name, class_def);
+        self.classes.insert(name, Rc::new(class_def));
     }
 
-    pub fn get_class(&self, name: &str) -> Option<&ClassDefinition> {
+    pub fn get_class(&self, name: &str) -> Option<&Rc<ClassDefinition>> {
         self.classes.get(name)
     }
 
@@ -113,454 +541,3952 @@ impl Environment {
     pub fn get_interface(&self, name: &str) -> Option<&InterfaceDefinition> {
         self.interfaces.get(name)
     }
+
+    pub fn define_module(&mut self, name: String) {
+        self.modules.insert(name);
+    }
+
+    pub fn is_module(&self, name: &str) -> bool {
+        self.modules.contains(name)
+    }
+
+    /// Lists every defined variable, function, class and interface, sorted by
+    /// name within each section, for the REPL's `:env` command.
+    pub fn describe(&self) -> String {
+        let mut variables: HashMap<&String, &Value> = HashMap::new();
+        for scope in &self.scopes {
+            variables.extend(scope.variables.iter());
+        }
+        let mut variables: Vec<_> = variables.into_iter().collect();
+        variables.sort_by_key(|(name, _)| *name);
+
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by_key(|(name, _)| *name);
+        let mut classes: Vec<_> = self.classes.values().collect();
+        classes.sort_by_key(|class_def| &class_def.name);
+        let mut interfaces: Vec<_> = self.interfaces.values().collect();
+        interfaces.sort_by_key(|interface_def| &interface_def.name);
+
+        let mut lines = vec!["Variables:".to_string()];
+        for (name, value) in variables {
+            let kind = if self.is_constant(name) { "const " } else { "" };
+            lines.push(format!("  {}{}: {} = {}", kind, name, value.type_name(), value));
+        }
+
+        lines.push("Functions:".to_string());
+        for (name, function) in functions {
+            if let Value::Function { parameters, return_type, .. } = function {
+                lines.push(format!(
+                    "  {}({}) -> {}",
+                    name,
+                    formatter::format_parameters(parameters),
+                    formatter::format_type(return_type)
+                ));
+            }
+        }
+
+        lines.push("Classes:".to_string());
+        for class_def in classes {
+            if class_def.implements.is_empty() {
+                lines.push(format!("  {}", class_def.name));
+            } else {
+                lines.push(format!("  {} implements {}", class_def.name, class_def.implements.join(", ")));
+            }
+        }
+
+        lines.push("Interfaces:".to_string());
+        for interface_def in interfaces {
+            lines.push(format!("  {}", interface_def.name));
+        }
+
+        lines.join("\n")
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub struct Interpreter {
     environment: Environment,
+    output: Box<dyn Write>,
+    /// Where `eprint` and `eprintln` write.
+    error_output: Box<dyn Write>,
+    // Position of the innermost statement that raised the current error
+    error_span: Option<Span>,
+    /// Names of the modules whose body is being run, outermost first.
+    module_path: Vec<String>,
+    /// Where `--trace` output goes, if tracing is on.
+    trace: Option<Box<dyn Write>>,
+    /// How many calls may be running at once before a call fails.
+    max_call_depth: usize,
+    /// The code given to `exit`, which unwinds the program like an error
+    /// that `try` can't catch.
+    exit_code: Option<i32>,
 }
 
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The default limit on nested calls, low enough that the interpreter's own
+/// recursion stays well within the Rust stack.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// The longest String, in bytes, that repeating one with `*` may build, so a
+/// huge count fails with an error instead of running out of memory.
+const MAX_REPEAT_LEN: usize = 1 << 28;
+
+/// The most elements `range` may build, for the same reason.
+const MAX_RANGE_LEN: i128 = 1 << 24;
+
 impl Interpreter {
     pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /// Creates an interpreter whose `print`/`write` output goes to `output`
+    /// instead of stdout.
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        Self::with_outputs(output, Box::new(io::stderr()))
+    }
+
+    /// Like `with_output`, but also sends `eprint`/`eprintln` output to
+    /// `error_output` instead of stderr.
+    pub fn with_outputs(output: Box<dyn Write>, error_output: Box<dyn Write>) -> Self {
         Self {
             environment: Environment::new(),
+            output,
+            error_output,
+            error_span: None,
+            module_path: Vec::new(),
+            trace: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            exit_code: None,
+        }
+    }
+
+    /// Sets how many calls may be nested before one fails with a recursion
+    /// error.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Turns on tracing: every statement run, and every function and method
+    /// entered and left, is written to `trace`.
+    pub fn trace_to(&mut self, trace: Box<dyn Write>) {
+        self.trace = Some(trace);
+    }
+
+    /// The code the program passed to `exit`, if it called it. The
+    /// interpreter never ends the process itself: `exit` stops the program
+    /// with an error, and it is up to the host to exit with this code.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Writes one line of trace output, indented by the call depth. The
+    /// message is only built when tracing is on.
+    fn trace(&mut self, message: impl FnOnce(&Self) -> String) -> Result<(), String> {
+        if self.trace.is_none() {
+            return Ok(());
+        }
+        let line = format!("{}{}", "  ".repeat(self.environment.call_depth()), message(self));
+        match &mut self.trace {
+            Some(trace) => writeln!(trace, "[trace] {}", line).map_err(|e| e.to_string()),
+            None => Ok(()),
         }
     }
 
     pub fn interpret(&mut self, program: Program) -> Result<(), String> {
-        for statement in program.statements {
-            self.execute_statement(&statement)?;
+        self.interpret_with_result(program).map(|_| ())
+    }
+
+    /// Like `interpret`, but also returns the value of the last statement when
+    /// it produced a non-Void value, so the REPL can echo it.
+    pub fn interpret_with_result(&mut self, program: Program) -> Result<Option<Value>, String> {
+        self.exit_code = None;
+
+        // Constants with constant values are defined before anything else
+        // runs, so code above their declaration can already use them
+        let (constants, statements) = hoist_constants(program.statements);
+        for constant in &constants {
+            self.execute_top_level(constant)?;
         }
-        Ok(())
+
+        let mut last_value = None;
+        for statement in statements {
+            last_value = match self.execute_top_level(&statement)? {
+                Some(Flow::Value(value)) => Some(value),
+                _ => None,
+            };
+        }
+        Ok(last_value)
     }
 
-    fn execute_statement(&mut self, statement: &Statement) -> Result<Option<Value>, String> {
+    /// Runs one top-level statement, adding the line that failed to any error.
+    fn execute_top_level(&mut self, statement: &Statement) -> Result<Option<Flow>, String> {
+        self.error_span = None;
+        self.execute_statement(statement).map_err(|err| match self.error_span.take() {
+            Some(span) => format!("{} at line {}", err, span.line),
+            None => err,
+        })
+    }
+
+    /// Runs a block in its own scope, which is dropped even if the block fails.
+    fn execute_block(&mut self, statements: &[Statement]) -> Result<Option<Flow>, String> {
+        self.environment.push_scope();
+        let result = self.execute_statements(statements);
+        self.environment.pop_scope();
+        result
+    }
+
+    /// Runs the statements of a block. A value only becomes the block's
+    /// result when its last statement produces it; one from an earlier
+    /// statement, such as a call made for its side effects, is discarded.
+    fn execute_statements(&mut self, statements: &[Statement]) -> Result<Option<Flow>, String> {
+        for (i, stmt) in statements.iter().enumerate() {
+            match self.execute_statement(stmt)? {
+                Some(Flow::Value(_)) if i + 1 < statements.len() => {}
+                Some(flow) => return Ok(Some(flow)),
+                None => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn execute_statement(&mut self, statement: &Statement) -> Result<Option<Flow>, String> {
+        self.trace(|_| {
+            let span = statement.span();
+            format!("{}:{}: {}", span.line, span.column, formatter::format_statement_line(statement))
+        })?;
+        let result = self.execute_statement_inner(statement);
+        // Errors are attributed to the innermost statement that failed, so a
+        // failure inside a function body points into the body, not the call
+        if result.is_err() && self.error_span.is_none() {
+            self.error_span = Some(statement.span());
+        }
+        result
+    }
+
+    fn execute_statement_inner(&mut self, statement: &Statement) -> Result<Option<Flow>, String> {
         match statement {
-            Statement::VariableDeclaration { name, var_type, value } => {
-                let val = self.evaluate_expression(value)?;
+            Statement::VariableDeclaration { name, var_type, value, .. } => {
+                let val = self.evaluate_declaration(name, var_type, value)?;
+                self.define_declared(name.clone(), val, var_type.clone());
+                Ok(None)
+            }
 
-                // Type checking
-                match (var_type, &val) {
-                    (Type::Int, Value::Integer(_)) => {},
-                    (Type::String, Value::String(_)) => {},
-                    (Type::Void, Value::Void) => {},
-                    (Type::Custom(class_name), Value::Object { class_name: obj_class, .. }) => {
-                        if class_name != obj_class {
-                            return Err(format!(
-                                "Type mismatch: expected {}, got {}",
-                                class_name,
-                                obj_class
-                            ));
-                        }
-                    },
-                    _ => return Err(format!(
-                        "Type mismatch: expected {:?}, got {}",
-                        var_type,
-                        val.type_name()
-                    )),
-                }
+            Statement::ConstDeclaration { name, var_type, value, .. } => {
+                let val = self.evaluate_declaration(name, var_type, value)?;
+                self.environment.define_constant(name.clone(), val);
+                Ok(None)
+            }
 
-                self.environment.define_variable(name.clone(), val);
+            Statement::MultipleDeclaration { declarations, .. } => {
+                for declaration in declarations {
+                    self.execute_statement(declaration)?;
+                }
                 Ok(None)
             }
 
-            Statement::FunctionDefinition { name, parameters, return_type, body } => {
+            Statement::FunctionDefinition { name, parameters, return_type, body, .. } => {
                 let function_value = Value::Function {
+                    name: self.qualify(name),
                     parameters: parameters.clone(),
                     return_type: return_type.clone(),
-                    body: body.clone(),
+                    body: body.clone().into(),
                 };
-                self.environment.define_function(name.clone(), function_value);
+                self.environment.define_function(self.qualify(name), function_value);
                 Ok(None)
             }
 
-            Statement::If { condition, then_branch, else_branch } => {
+            Statement::If { condition, then_branch, else_branch, .. } => {
                 let condition_value = self.evaluate_expression(condition)?;
 
-                let should_execute_then = match condition_value {
-                    Value::Integer(n) => n != 0,
-                    Value::String(s) => !s.is_empty(),
-                    Value::Function { .. } => true,
-                    Value::Object { .. } => true,
-                    Value::Void => false,
-                };
+                if self.is_truthy(&condition_value) {
+                    self.execute_block(then_branch)
+                } else if let Some(else_stmts) = else_branch {
+                    self.execute_block(else_stmts)
+                } else {
+                    Ok(None)
+                }
+            }
 
-                if should_execute_then {
-                    for stmt in then_branch {
-                        if let Some(return_value) = self.execute_statement(stmt)? {
-                            return Ok(Some(return_value));
-                        }
+            Statement::While { condition, body, .. } => {
+                loop {
+                    let condition_value = self.evaluate_expression(condition)?;
+                    if !self.is_truthy(&condition_value) {
+                        break;
                     }
-                } else if let Some(else_stmts) = else_branch {
-                    for stmt in else_stmts {
-                        if let Some(return_value) = self.execute_statement(stmt)? {
-                            return Ok(Some(return_value));
-                        }
+                    // Each iteration gets a fresh scope
+                    match self.execute_block(body)? {
+                        Some(Flow::Break) => break,
+                        Some(Flow::Continue | Flow::Value(_)) | None => {}
+                        Some(flow) => return Ok(Some(flow)),
                     }
                 }
                 Ok(None)
             }
 
-            Statement::Expression(expr) => {
-                let value = self.evaluate_expression(expr)?;
-                Ok(Some(value))
+            Statement::RepeatUntil { body, condition, .. } => {
+                loop {
+                    // `continue` skips to the condition check
+                    match self.execute_block(body)? {
+                        Some(Flow::Break) => break,
+                        Some(Flow::Continue | Flow::Value(_)) | None => {}
+                        Some(flow) => return Ok(Some(flow)),
+                    }
+                    let condition_value = self.evaluate_expression(condition)?;
+                    if self.is_truthy(&condition_value) {
+                        break;
+                    }
+                }
+                Ok(None)
+            }
+
+            Statement::Match { scrutinee, cases, default, .. } => {
+                let scrutinee_value = self.evaluate_expression(scrutinee)?;
+
+                let mut matched = None;
+                for case in cases {
+                    let case_value = self.evaluate_expression(&case.value)?;
+                    let equal = self.equal_values(scrutinee_value.clone(), case_value)?;
+                    if self.is_truthy(&equal) {
+                        matched = Some(&case.body);
+                        break;
+                    }
+                }
+
+                match matched.or(default.as_ref()) {
+                    Some(body) => self.execute_block(body),
+                    None => Ok(None),
+                }
+            }
+
+            Statement::Block { body, .. } => self.execute_block(body),
+
+            Statement::Try { body, catch_var, catch_body, .. } => {
+                match self.execute_block(body) {
+                    Err(message) if self.exit_code.is_none() => {
+                        // The error was handled, so it no longer points at a statement
+                        self.error_span = None;
+
+                        // The caught message is only visible inside the catch body
+                        self.environment.push_scope();
+                        self.environment.define_variable(catch_var.clone(), Value::String(message));
+                        let result = self.execute_statements(catch_body);
+                        self.environment.pop_scope();
+                        result
+                    }
+                    result => result,
+                }
+            }
+
+            Statement::Raise { message, .. } => {
+                let message = self.evaluate_expression(message)?;
+                Err(message.to_string())
+            }
+
+            Statement::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.evaluate_expression(value)?,
+                    None => Value::Void,
+                };
+                Ok(Some(Flow::Return(value)))
+            }
+
+            Statement::Break(_) => Ok(Some(Flow::Break)),
+
+            Statement::Continue(_) => Ok(Some(Flow::Continue)),
+
+            Statement::Expression(expr, _) => {
+                // A Void expression, such as a call to print, produces no result,
+                // so it doesn't end the enclosing function body
+                match self.evaluate_expression(expr)? {
+                    Value::Void => Ok(None),
+                    value => Ok(Some(Flow::Value(value))),
+                }
             }
 
-            Statement::ClassDefinition { name, fields, methods, implements } => {
+            Statement::ClassDefinition { name, fields, methods, implements, .. } => {
+                let name = self.qualify(name);
+                self.check_not_a_variable("Class", &name)?;
                 let class_def = ClassDefinition {
                     name: name.clone(),
                     fields: fields.clone(),
                     methods: methods.clone(),
                     implements: implements.clone(),
                 };
-                self.environment.define_class(name.clone(), class_def);
+                self.environment.define_class(name, class_def);
                 Ok(None)
             }
 
-            Statement::InterfaceDefinition { name, methods } => {
+            Statement::InterfaceDefinition { name, methods, .. } => {
+                let name = self.qualify(name);
+                self.check_not_a_variable("Interface", &name)?;
                 let interface_def = InterfaceDefinition {
                     name: name.clone(),
                     methods: methods.clone(),
                 };
-                self.environment.define_interface(name.clone(), interface_def);
+                self.environment.define_interface(name, interface_def);
                 Ok(None)
             }
 
-            Statement::Assignment { target, value } => {
-                let val = self.evaluate_expression(value)?;
+            Statement::Module { name, body, .. } => {
+                self.check_not_a_variable("Module", &self.qualify(name))?;
+                self.environment.define_module(self.qualify(name));
+                self.module_path.push(name.clone());
+                let result = body.iter().try_for_each(|stmt| self.execute_statement(stmt).map(|_| ()));
+                self.module_path.pop();
+                result.map(|_| None)
+            }
 
-                // For now, only support simple identifier assignments
-                if let Expression::Identifier(name) = target {
-                    self.environment.define_variable(name.clone(), val);
-                    Ok(None)
-                } else {
-                    Err("Only simple variable assignments are supported currently".to_string())
-                }
+            Statement::Assignment { target, value, .. } => {
+                let val = self.evaluate_expression(value)?;
+                self.assign_to(target, val)?;
+                Ok(None)
             }
         }
     }
 
-    fn evaluate_expression(&mut self, expression: &Expression) -> Result<Value, String> {
-        match expression {
-            Expression::Integer(n) => Ok(Value::Integer(*n)),
+    /// The full name of a definition made in the module being run.
+    fn qualify(&self, name: &str) -> String {
+        if self.module_path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", self.module_path.join("."), name)
+        }
+    }
 
-            Expression::String(s) => {
-                // Handle simple string interpolation #{variable}
-                if s.contains("#{") {
-                    let mut result = s.clone();
-                    // Simple regex-like replacement for #{name}
-                    if let Some(start) = result.find("#{") {
-                        if let Some(end) = result.find('}') {
-                            let var_name = &result[start + 2..end];
-                            if let Some(value) = self.environment.get_variable(var_name) {
-                                let replacement = value.to_string();
-                                result.replace_range(start..=end, &replacement);
-                            }
-                        }
-                    }
-                    Ok(Value::String(result))
-                } else {
-                    Ok(Value::String(s.clone()))
-                }
-            }
+    /// A type or module named like an existing variable would make
+    /// `Name.new()` ambiguous, the same conflict a declaration checks for.
+    fn check_not_a_variable(&self, kind: &str, name: &str) -> Result<(), String> {
+        if self.environment.get_variable(name).is_some() {
+            return Err(format!("{} {} conflicts with variable {}", kind, name, name));
+        }
+        Ok(())
+    }
 
-            Expression::Identifier(name) => {
-                if let Some(value) = self.environment.get_variable(name) {
-                    Ok(value.clone())
-                } else {
-                    Err(format!("Undefined variable: {}", name))
+    fn is_truthy(&self, value: &Value) -> bool {
+        match value {
+            Value::Integer(n) => *n != 0,
+            Value::Float(n) => *n != 0.0,
+            Value::String(s) => !s.is_empty(),
+            Value::Char(_) => true,
+            Value::Map(entries) => !entries.is_empty(),
+            Value::List(elements) => !elements.is_empty(),
+            Value::Function { .. } => true,
+            Value::Object { .. } => true,
+            Value::Void => false,
+        }
+    }
+
+    /// Custom types must name a class or interface that has been defined by the
+    /// time the declaration or call runs, including inside `List<T>` and `Map<T>`.
+    fn check_type_defined(&self, type_: &Type) -> Result<(), String> {
+        match type_ {
+            Type::Custom(name) => {
+                if self.environment.get_class(name).is_none() && self.environment.get_interface(name).is_none() {
+                    return Err(format!("Unknown type {}", name));
                 }
+                Ok(())
             }
+            Type::ListOf(element_type) | Type::MapOf(element_type) => self.check_type_defined(element_type),
+            _ => Ok(()),
+        }
+    }
 
-            Expression::Binary { left, operator, right } => {
-                let left_val = self.evaluate_expression(left)?;
-                let right_val = self.evaluate_expression(right)?;
+    /// Runs a function or method body in a new call frame, which is dropped
+    /// even when the body fails, so a caught error doesn't leak the callee's
+    /// variables. Methods get their receiver as `this`.
+    fn call(
+        &mut self,
+        name: &str,
+        parameters: &[Parameter],
+        arg_values: Vec<Option<Value>>,
+        return_type: &Type,
+        body: &[Statement],
+        this: Option<Value>,
+    ) -> Result<Value, String> {
+        if self.environment.call_depth() >= self.max_call_depth {
+            return Err(format!("Maximum recursion depth exceeded ({} calls) in {}", self.max_call_depth, name));
+        }
+        self.environment.push_frame();
 
-                match operator {
-                    BinaryOperator::Add => self.add_values(left_val, right_val),
-                    BinaryOperator::Subtract => self.subtract_values(left_val, right_val),
-                    BinaryOperator::Multiply => self.multiply_values(left_val, right_val),
-                    BinaryOperator::Divide => self.divide_values(left_val, right_val),
-                    BinaryOperator::Equal => self.equal_values(left_val, right_val),
-                }
+        if let Some(this) = this {
+            self.environment.define_variable("this".to_string(), this);
+        }
+
+        let result = self.bind_parameters(parameters, arg_values).and_then(|_| {
+            self.trace(|interpreter| {
+                let arguments: Vec<String> = parameters
+                    .iter()
+                    .map(|param| match interpreter.environment.get_variable(&param.name) {
+                        Some(value) => format!("{} = {}", param.name, value),
+                        None => param.name.clone(),
+                    })
+                    .collect();
+                format!("-> {}({})", name, arguments.join(", "))
+            })?;
+            self.execute_body(return_type, body)
+        });
+        let traced = self.trace(|_| match &result {
+            Ok(Value::Void) => format!("<- {}", name),
+            Ok(value) => format!("<- {} = {}", name, value),
+            Err(err) => format!("<- {} failed: {}", name, err),
+        });
+        let result = traced.and(result);
+
+        self.environment.pop_frame();
+        result
+    }
+
+    /// Finds a method on a class, falling back to a default body from the
+    /// interfaces it implements, in the order they are listed. Defaults are
+    /// copied out of the interface, so only a class's own methods are borrowed.
+    fn find_method<'a>(&self, class_def: &'a ClassDefinition, name: &str) -> Option<Cow<'a, Method>> {
+        if let Some(method) = class_def.methods.iter().find(|method| method.name == name) {
+            return Some(Cow::Borrowed(method));
+        }
+
+        class_def.implements.iter().find_map(|interface| {
+            let interface_def = self.environment.get_interface(interface)?;
+            let method = interface_def.methods.iter().find(|method| method.name == name)?;
+            Some(Cow::Owned(Method {
+                name: method.name.clone(),
+                is_static: false,
+                parameters: method.parameters.clone(),
+                return_type: method.return_type.clone(),
+                body: method.body.clone()?,
+            }))
+        })
+    }
+
+    /// Calls a user-defined function by its full name.
+    fn call_function(
+        &mut self,
+        name: &str,
+        arguments: &[Expression],
+        keyword_arguments: &[KeywordArgument],
+    ) -> Result<Value, String> {
+        let Some(function) = self.environment.get_function(name).cloned() else {
+            return Err(format!("Undefined function: {}", name));
+        };
+        self.call_function_value(function, arguments, keyword_arguments)
+    }
+
+    /// Calls a function value, which may be a built-in or a user-defined
+    /// function.
+    fn call_function_value(
+        &mut self,
+        function: Value,
+        arguments: &[Expression],
+        keyword_arguments: &[KeywordArgument],
+    ) -> Result<Value, String> {
+        let Value::Function { name, parameters, return_type, body } = function else {
+            return Err(format!("Cannot call a value of type {}", function.type_name()));
+        };
+        if self.environment.is_builtin(&name) {
+            if !keyword_arguments.is_empty() {
+                return Err(format!("{}() does not take keyword arguments", name));
             }
+            return self.call_builtin(&name, arguments);
+        }
 
-            Expression::FunctionCall { name, arguments } => {
-                // Handle built-in print function
-                if name == "print" {
-                    if arguments.len() != 1 {
-                        return Err("print() takes exactly one argument".to_string());
-                    }
+        let arg_values = self.evaluate_arguments("Function", &name, &parameters, arguments, keyword_arguments)?;
+        for param in &parameters {
+            self.check_type_defined(&param.param_type)?;
+        }
+        self.check_type_defined(&return_type)?;
 
-                    let value = self.evaluate_expression(&arguments[0])?;
-                    println!("{}", value.to_string());
-                    return Ok(Value::String("".to_string()));
-                }
+        self.call(&name, &parameters, arg_values, &return_type, &body, None)
+    }
 
-                // Handle user-defined functions
-                if let Some(function) = self.environment.get_function(name).cloned() {
-                    if let Value::Function { parameters, return_type, body } = function {
-                        if arguments.len() != parameters.len() {
-                            return Err(format!(
-                                "Function {} expects {} arguments, got {}",
-                                name,
-                                parameters.len(),
-                                arguments.len()
-                            ));
-                        }
+    /// Runs a built-in function called with argument expressions. Only
+    /// `assert` needs them unevaluated, so its message is only evaluated when
+    /// the assertion fails; every other built-in gets its argument values.
+    fn call_builtin(&mut self, name: &str, arguments: &[Expression]) -> Result<Value, String> {
+        if name == "assert" {
+            let (condition, message) = match arguments {
+                [condition] => (condition, None),
+                [condition, message] => (condition, Some(message)),
+                _ => return Err("assert() takes one or two arguments".to_string()),
+            };
 
-                        // Create new scope for function execution
-                        let old_vars = self.environment.variables.clone();
-
-                        // Bind arguments to parameters
-                        for (param, arg) in parameters.iter().zip(arguments.iter()) {
-                            let arg_value = self.evaluate_expression(arg)?;
-
-                            // Type checking
-                            match (&param.param_type, &arg_value) {
-                                (Type::Int, Value::Integer(_)) => {},
-                                (Type::String, Value::String(_)) => {},
-                                (Type::Void, Value::Void) => {},
-                                (Type::Custom(class_name), Value::Object { class_name: obj_class, .. }) => {
-                                    if class_name != obj_class {
-                                        return Err(format!(
-                                            "Argument type mismatch for parameter {}: expected {}, got {}",
-                                            param.name,
-                                            class_name,
-                                            obj_class
-                                        ));
-                                    }
-                                },
-                                _ => return Err(format!(
-                                    "Argument type mismatch for parameter {}: expected {:?}, got {}",
-                                    param.name,
-                                    param.param_type,
-                                    arg_value.type_name()
-                                )),
-                            }
+            let condition = self.evaluate_expression(condition)?;
+            if self.is_truthy(&condition) {
+                return Ok(Value::Void);
+            }
+            return match message {
+                Some(message) => Err(format!("Assertion failed: {}", self.evaluate_expression(message)?)),
+                None => Err("Assertion failed".to_string()),
+            };
+        }
 
-                            self.environment.define_variable(param.name.clone(), arg_value);
-                        }
+        let mut values = Vec::with_capacity(arguments.len());
+        for argument in arguments {
+            values.push(self.evaluate_expression(argument)?);
+        }
+        self.call_builtin_with(name, values)
+    }
 
-                        // Execute function body
-                        let mut result = match return_type {
-                            Type::Int => Value::Integer(0),
-                            Type::String => Value::String("".to_string()),
-                            Type::Void => Value::Void,
-                            Type::Custom(_) => Value::Void, // Default for custom types
-                        };
+    /// Runs a built-in function on argument values.
+    fn call_builtin_with(&mut self, name: &str, values: Vec<Value>) -> Result<Value, String> {
+        // Handle built-in print function
+        if name == "print" {
+            // Any number of arguments, separated by spaces
+            let mut parts = Vec::with_capacity(values.len());
+            for value in values {
+                parts.push(self.stringify(value)?);
+            }
 
-                        for stmt in &body {
-                            if let Some(return_value) = self.execute_statement(stmt)? {
-                                result = return_value;
-                                break;
-                            }
-                        }
+            writeln!(self.output, "{}", parts.join(" ")).map_err(|e| e.to_string())?;
+            return Ok(Value::Void);
+        }
+
+        // Handle built-in eprint and eprintln functions, which print to the error output
+        if matches!(name, "eprint" | "eprintln") {
+            let mut parts = Vec::with_capacity(values.len());
+            for value in values {
+                parts.push(self.stringify(value)?);
+            }
+
+            let newline = if name == "eprintln" { "\n" } else { "" };
+            write!(self.error_output, "{}{}", parts.join(" "), newline).map_err(|e| e.to_string())?;
+            self.error_output.flush().map_err(|e| e.to_string())?;
+            return Ok(Value::Void);
+        }
+
+        // Handle built-in write function (print without a trailing newline)
+        if name == "write" {
+            let [value] = &values[..] else {
+                return Err("write() takes exactly one argument".to_string());
+            };
+
+            let text = self.stringify(value.clone())?;
+            write!(self.output, "{}", text).map_err(|e| e.to_string())?;
+            self.output.flush().map_err(|e| e.to_string())?;
+            return Ok(Value::Void);
+        }
+
+        // Handle built-in intdiv function
+        if name == "intdiv" {
+            let [left, right] = &values[..] else {
+                return Err("intdiv() takes exactly two arguments".to_string());
+            };
+
+            return self.int_divide_values(left.clone(), right.clone());
+        }
+
+        // Handle built-in type function
+        if name == "type" {
+            let [value] = &values[..] else {
+                return Err("type() takes exactly one argument".to_string());
+            };
 
-                        // Restore old scope
-                        self.environment.variables = old_vars;
+            return Ok(Value::String(value.type_name()));
+        }
+
+        // Handle built-in exit function
+        if name == "exit" {
+            let [value] = &values[..] else {
+                return Err("exit() takes exactly one argument".to_string());
+            };
+
+            let code = match value {
+                Value::Integer(code) if (0..=255).contains(code) => *code as i32,
+                Value::Integer(code) => return Err(format!("exit() expects a code from 0 to 255, got {}", code)),
+                other => return Err(format!("exit() expects an Int, got {}", other.type_name())),
+            };
+            self.output.flush().map_err(|e| e.to_string())?;
+            self.error_output.flush().map_err(|e| e.to_string())?;
+            self.exit_code = Some(code);
+            return Err(format!("Program exited with code {}", code));
+        }
+
+        // Handle built-in clone function
+        if name == "clone" {
+            let [value] = &values[..] else {
+                return Err("clone() takes exactly one argument".to_string());
+            };
+
+            return Ok(deep_copy(value, &mut HashMap::new()));
+        }
+
+        // Handle built-in type predicates
+        if matches!(name, "isInt" | "isString" | "isObject") {
+            let [value] = &values[..] else {
+                return Err(format!("{}() takes exactly one argument", name));
+            };
 
-                        Ok(result)
+            let matches = matches!(
+                (name, value),
+                ("isInt", Value::Integer(_))
+                    | ("isString", Value::String(_))
+                    | ("isObject", Value::Object { .. })
+            );
+            return Ok(Value::Integer(if matches { 1 } else { 0 }));
+        }
+
+        // Handle built-in rounding functions
+        if matches!(name, "floor" | "ceil" | "round") {
+            let [value] = &values[..] else {
+                return Err(format!("{}() takes exactly one argument", name));
+            };
+
+            return match value {
+                Value::Integer(n) => Ok(Value::Integer(*n)),
+                Value::Float(x) => {
+                    // `round` rounds halves away from zero
+                    let rounded = match name {
+                        "floor" => x.floor(),
+                        "ceil" => x.ceil(),
+                        _ => x.round(),
+                    };
+                    // i64::MAX as f64 rounds up to 2^63, so that bound is exclusive
+                    if rounded.is_finite() && rounded >= i64::MIN as f64 && rounded < i64::MAX as f64 {
+                        Ok(Value::Integer(rounded as i64))
                     } else {
-                        unreachable!("Function value should be Function variant")
+                        Err(format!("{}({}) is out of the Int range", name, Value::Float(*x)))
                     }
-                } else {
-                    Err(format!("Undefined function: {}", name))
+                }
+                other => Err(format!("{}() expects a number, got {}", name, other.type_name())),
+            };
+        }
+
+        // Handle built-in toInt function
+        if name == "toInt" {
+            let [value] = &values[..] else {
+                return Err("toInt() takes exactly one argument".to_string());
+            };
+
+            return match value {
+                Value::Integer(n) => Ok(Value::Integer(*n)),
+                Value::String(text) => parse_int(text).map(Value::Integer),
+                other => Err(format!("toInt() expects a String or Int, got {}", other.type_name())),
+            };
+        }
+
+        // Handle built-in range function
+        if name == "range" {
+            let mut bounds = Vec::with_capacity(values.len());
+            for value in &values {
+                match value {
+                    Value::Integer(n) => bounds.push(*n),
+                    other => return Err(format!(
+                        "range() expects Int arguments, got {}",
+                        other.type_name()
+                    )),
                 }
             }
 
-            Expression::StringInterpolation { parts: _ } => {
-                // TODO: Implement string interpolation
-                Ok(Value::String("".to_string()))
+            let (start, end) = match bounds[..] {
+                [end] => (0, end),
+                [start, end] => (start, end),
+                _ => return Err("range() takes one or two arguments".to_string()),
+            };
+            if end as i128 - start as i128 > MAX_RANGE_LEN {
+                return Err(format!("range({}, {}) would have more than {} elements", start, end, MAX_RANGE_LEN));
             }
+            return Ok(Value::List((start..end).map(Value::Integer).collect()));
+        }
 
-            Expression::MethodCall { object, method, arguments } => {
-                let obj_value = self.evaluate_expression(object)?;
+        // Handle built-in assert function, here called through a function value
+        if name == "assert" {
+            let (condition, message) = match &values[..] {
+                [condition] => (condition, None),
+                [condition, message] => (condition, Some(message)),
+                _ => return Err("assert() takes one or two arguments".to_string()),
+            };
 
-                if let Value::Object { class_name, fields } = obj_value {
-                    if let Some(class_def) = self.environment.get_class(&class_name).cloned() {
-                        // Find the method in the class
-                        for method_def in &class_def.methods {
-                            if method_def.name == *method {
-                                // Check argument count
-                                if arguments.len() != method_def.parameters.len() {
-                                    return Err(format!(
-                                        "Method {} expects {} arguments, got {}",
-                                        method,
-                                        method_def.parameters.len(),
-                                        arguments.len()
-                                    ));
-                                }
-
-                                // Create new scope for method execution
-                                let old_vars = self.environment.variables.clone();
-
-                                // Add 'this' reference
-                                self.environment.define_variable("this".to_string(), Value::Object {
-                                    class_name: class_name.clone(),
-                                    fields: fields.clone(),
-                                });
-
-                                // Bind arguments to parameters
-                                for (param, arg) in method_def.parameters.iter().zip(arguments.iter()) {
-                                    let arg_value = self.evaluate_expression(arg)?;
-                                    self.environment.define_variable(param.name.clone(), arg_value);
-                                }
-
-                                // Execute method body
-                                let mut result = match &method_def.return_type {
-                                    Type::Int => Value::Integer(0),
-                                    Type::String => Value::String("".to_string()),
-                                    Type::Void => Value::Void,
-                                    Type::Custom(_) => Value::Void, // Default for custom types
-                                };
-
-                                for stmt in &method_def.body {
-                                    if let Some(return_value) = self.execute_statement(stmt)? {
-                                        result = return_value;
-                                        break;
-                                    }
-                                }
-
-                                // Restore old scope
-                                self.environment.variables = old_vars;
-
-                                return Ok(result);
-                            }
+            if self.is_truthy(condition) {
+                return Ok(Value::Void);
+            }
+            return match message {
+                Some(message) => Err(format!("Assertion failed: {}", message)),
+                None => Err("Assertion failed".to_string()),
+            };
+        }
+
+        // Handle built-in len function
+        if name == "len" {
+            let [value] = &values[..] else {
+                return Err("len() takes exactly one argument".to_string());
+            };
+
+            return match value {
+                Value::Map(entries) => Ok(Value::Integer(entries.len() as i64)),
+                Value::List(elements) => Ok(Value::Integer(elements.len() as i64)),
+                // Count characters, not UTF-8 bytes, to match indexing
+                Value::String(s) => Ok(Value::Integer(s.chars().count() as i64)),
+                other => Err(format!("Cannot take len() of {}", other.type_name())),
+            };
+        }
+
+        // Handle built-in map, filter and reduce, which call a function on each element
+        if matches!(name, "map" | "filter" | "reduce") {
+            let expected = if name == "reduce" { 3 } else { 2 };
+            if values.len() != expected {
+                let count = if name == "reduce" { "three" } else { "two" };
+                return Err(format!("{}() takes exactly {} arguments", name, count));
+            }
+
+            let mut values = values.into_iter();
+            let elements = match values.next() {
+                Some(Value::List(elements)) => elements,
+                Some(other) => return Err(format!("{}() expects a List, got {}", name, other.type_name())),
+                None => unreachable!("the argument count was checked"),
+            };
+            let function = values.next().unwrap_or(Value::Void);
+            self.check_callback(name, &function, if name == "reduce" { 2 } else { 1 })?;
+
+            return match name {
+                "map" => {
+                    let mut results = Vec::with_capacity(elements.len());
+                    for element in elements {
+                        results.push(self.apply_function(&function, vec![element])?);
+                    }
+                    Ok(Value::List(results))
+                }
+                "filter" => {
+                    let mut kept = Vec::new();
+                    for element in elements {
+                        let keep = self.apply_function(&function, vec![element.clone()])?;
+                        if self.is_truthy(&keep) {
+                            kept.push(element);
                         }
-                        Err(format!("Method {} not found in class {}", method, class_name))
-                    } else {
-                        Err(format!("Class {} not found", class_name))
                     }
-                } else {
-                    Err(format!("Cannot call method on non-object value"))
+                    Ok(Value::List(kept))
                 }
+                _ => {
+                    let mut accumulator = values.next().unwrap_or(Value::Void);
+                    for element in elements {
+                        accumulator = self.apply_function(&function, vec![accumulator, element])?;
+                    }
+                    Ok(accumulator)
+                }
+            };
+        }
+
+        Err(format!("Undefined function: {}", name))
+    }
+
+    /// Checks that a value passed to a built-in such as `map` is a function
+    /// that can be called with `arity` arguments.
+    fn check_callback(&self, builtin: &str, function: &Value, arity: usize) -> Result<(), String> {
+        let Value::Function { name, parameters, .. } = function else {
+            return Err(format!(
+                "{}() expects a Function as its second argument, got {}",
+                builtin,
+                function.type_name()
+            ));
+        };
+
+        let required = parameters.iter().filter(|p| p.default.is_none() && !p.variadic).count();
+        let variadic = parameters.last().is_some_and(|p| p.variadic);
+        if arity < required || (!variadic && arity > parameters.len()) {
+            let arguments = if arity == 1 { "argument" } else { "arguments" };
+            return Err(format!(
+                "{}() expects a function that takes {} {}, but {} takes {}",
+                builtin,
+                arity,
+                arguments,
+                name,
+                describe_arity(parameters)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Calls a function value with arguments that have already been
+    /// evaluated, filling parameters from the left.
+    fn apply_function(&mut self, function: &Value, values: Vec<Value>) -> Result<Value, String> {
+        let Value::Function { name, parameters, return_type, body } = function else {
+            return Err(format!("Cannot call a value of type {}", function.type_name()));
+        };
+        if self.environment.is_builtin(name) {
+            return self.call_builtin_with(name, values);
+        }
+
+        let mut values = values.into_iter();
+        let mut arg_values = Vec::with_capacity(parameters.len());
+        for param in parameters {
+            if param.variadic {
+                arg_values.push(Some(Value::List(values.by_ref().collect())));
+            } else {
+                arg_values.push(values.next());
             }
+        }
+        for param in parameters {
+            self.check_type_defined(&param.param_type)?;
+        }
+        self.check_type_defined(return_type)?;
 
-            Expression::FieldAccess { object, field } => {
-                let obj_value = self.evaluate_expression(object)?;
+        self.call(name, parameters, arg_values, return_type, body, None)
+    }
 
-                if let Value::Object { class_name: _, fields } = obj_value {
-                    if let Some(field_value) = fields.get(field) {
-                        Ok(field_value.clone())
-                    } else {
-                        Err(format!("Field {} not found", field))
+    /// Calls a method declared `static` on its class. The body runs without
+    /// a `this`.
+    fn call_static_method(
+        &mut self,
+        class_def: &ClassDefinition,
+        method: &str,
+        arguments: &[Expression],
+        keyword_arguments: &[KeywordArgument],
+    ) -> Result<Value, String> {
+        let Some(method_def) = class_def.methods.iter().find(|m| m.name == method) else {
+            return Err(format!("Method {} not found in class {}", method, class_def.name));
+        };
+        if !method_def.is_static {
+            return Err(format!("Method {} of class {} is not static", method, class_def.name));
+        }
+
+        let arg_values = self.evaluate_arguments("Method", method, &method_def.parameters, arguments, keyword_arguments)?;
+        for param in &method_def.parameters {
+            self.check_type_defined(&param.param_type)?;
+        }
+        self.check_type_defined(&method_def.return_type)?;
+
+        let name = format!("{}.{}", class_def.name, method);
+        self.call(&name, &method_def.parameters, arg_values, &method_def.return_type, &method_def.body, None)
+    }
+
+    /// Converts a value to the text `print` and `write` show. An object whose
+    /// class defines `toString() : String` is shown as that method's result;
+    /// anything else uses its `Display` form.
+    fn stringify(&mut self, value: Value) -> Result<String, String> {
+        let Value::Object { class_name, .. } = &value else {
+            return Ok(value.to_string());
+        };
+        let Some(class_def) = self.environment.get_class(class_name).cloned() else {
+            return Ok(value.to_string());
+        };
+        let to_string = self.find_method(&class_def, "toString").filter(|method| {
+            !method.is_static && method.parameters.is_empty() && method.return_type == Type::String
+        });
+
+        match to_string {
+            Some(method) => {
+                let name = format!("{}.toString", class_def.name);
+                let result = self.call(&name, &[], Vec::new(), &method.return_type, &method.body, Some(value.clone()))?;
+                Ok(result.to_string())
+            }
+            None => Ok(value.to_string()),
+        }
+    }
+
+    /// Binds argument slots from `evaluate_arguments` to parameters, filling
+    /// in defaults for empty ones. Each element of a variadic parameter's
+    /// List is checked against the parameter's type.
+    fn bind_parameters(&mut self, parameters: &[Parameter], arg_values: Vec<Option<Value>>) -> Result<(), String> {
+        for (param, value) in parameters.iter().zip(arg_values) {
+            let arg_value = match value {
+                Some(value) => value,
+                None => self.evaluate_default(param)?,
+            };
+
+            match &arg_value {
+                Value::List(rest) if param.variadic => {
+                    for value in rest {
+                        self.check_argument_type(param, value)?;
                     }
-                } else {
-                    Err(format!("Cannot access field on non-object value"))
                 }
+                value => self.check_argument_type(param, value)?,
             }
 
-            Expression::ObjectCreation { class_name } => {
-                if let Some(class_def) = self.environment.get_class(class_name).cloned() {
-                    let mut fields = HashMap::new();
+            let param_type = if param.variadic {
+                Type::ListOf(Box::new(param.param_type.clone()))
+            } else {
+                param.param_type.clone()
+            };
+            self.define_declared(param.name.clone(), arg_value, param_type);
+        }
+        Ok(())
+    }
 
-                    // Initialize fields with default values
-                    for field in &class_def.fields {
-                        let default_value = match &field.field_type {
-                            Type::Int => Value::Integer(0),
-                            Type::String => Value::String("".to_string()),
-                            Type::Void => Value::Void,
-                            Type::Custom(_) => Value::Void,
-                        };
-                        fields.insert(field.name.clone(), default_value);
-                    }
+    /// Defines a variable, remembering its type if it is a `List<T>` or
+    /// `Map<T>` that later assignments must match.
+    fn define_declared(&mut self, name: String, value: Value, var_type: Type) {
+        match var_type {
+            Type::ListOf(_) | Type::MapOf(_) => self.environment.define_typed_variable(name, value, var_type),
+            _ => self.environment.define_variable(name, value),
+        }
+    }
 
-                    // TODO: Handle constructor arguments
-                    // For now, just create the object with default field values
+    /// A body's result is the value of its first statement that produces one.
+    /// The parser rejects non-void bodies that can't produce a value, but an
+    /// expression statement can still turn out to be void at run time, which
+    /// is an error rather than a silent default.
+    fn execute_body(&mut self, return_type: &Type, body: &[Statement]) -> Result<Value, String> {
+        for stmt in body {
+            match self.execute_statement(stmt)? {
+                Some(Flow::Value(return_value) | Flow::Return(return_value)) => return Ok(return_value),
+                Some(_) => unreachable!("the parser rejects break and continue outside loops"),
+                None => {}
+            }
+        }
 
-                    Ok(Value::Object {
-                        class_name: class_name.clone(),
-                        fields,
-                    })
-                } else {
-                    Err(format!("Class {} not found", class_name))
-                }
+        match return_type {
+            Type::Void => Ok(Value::Void),
+            _ => Err(format!("Missing return value: expected {}", formatter::format_type(return_type))),
+        }
+    }
+
+    /// Evaluates call arguments in the caller's scope, returning one slot per
+    /// parameter, in order. Positional arguments fill parameters from the
+    /// left and keyword arguments fill the parameter they name; a slot left
+    /// empty takes the parameter's default. A variadic parameter collects the
+    /// remaining positional arguments into a List.
+    fn evaluate_arguments(
+        &mut self,
+        kind: &str,
+        name: &str,
+        parameters: &[Parameter],
+        arguments: &[Expression],
+        keyword_arguments: &[KeywordArgument],
+    ) -> Result<Vec<Option<Value>>, String> {
+        let required = parameters.iter().filter(|p| p.default.is_none() && !p.variadic).count();
+        let variadic = parameters.last().is_some_and(|p| p.variadic);
+        let too_few = keyword_arguments.is_empty() && arguments.len() < required;
+        if too_few || (!variadic && arguments.len() > parameters.len()) {
+            return Err(format!(
+                "{} {} expects {} arguments, got {}",
+                kind,
+                name,
+                describe_arity(parameters),
+                arguments.len() + keyword_arguments.len()
+            ));
+        }
+
+        let mut values = vec![None; parameters.len()];
+        let mut positional = arguments.iter();
+        for (slot, param) in values.iter_mut().zip(parameters) {
+            if param.variadic {
+                let rest = positional
+                    .by_ref()
+                    .map(|arg| self.evaluate_expression(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                *slot = Some(Value::List(rest));
+            } else if let Some(arg) = positional.next() {
+                *slot = Some(self.evaluate_expression(arg)?);
+            }
+        }
+
+        for keyword in keyword_arguments {
+            let index = parameters
+                .iter()
+                .position(|param| param.name == keyword.name)
+                .ok_or_else(|| format!("{} {} has no parameter named {}", kind, name, keyword.name))?;
+            if parameters[index].variadic {
+                return Err(format!("Variadic parameter {} cannot be passed by name", keyword.name));
             }
+            if values[index].is_some() {
+                return Err(format!("Argument {} is given more than once", keyword.name));
+            }
+            values[index] = Some(self.evaluate_expression(&keyword.value)?);
+        }
+
+        if let Some(missing) = parameters
+            .iter()
+            .zip(&values)
+            .find(|(param, value)| value.is_none() && param.default.is_none())
+        {
+            return Err(format!("{} {} is missing argument {}", kind, name, missing.0.name));
         }
+
+        Ok(values)
     }
 
-    fn add_values(&self, left: Value, right: Value) -> Result<Value, String> {
-        match (left, right) {
-            (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(a + b)),
-            (Value::String(a), Value::String(b)) => Ok(Value::String(a + &b)),
-            (left, right) => Err(format!(
-                "Cannot add {} and {}",
-                left.type_name(),
-                right.type_name()
-            )),
+    /// A field initializer runs in its own frame, like a call: it sees the
+    /// variables visible where the object is created, and any assignment it
+    /// makes to them is dropped afterwards. `this` is the object being
+    /// created, with only the fields declared above this one set.
+    fn evaluate_field_default(&mut self, field: &ClassField, default: &Expression, this: &Value) -> Result<Value, String> {
+        self.environment.push_frame();
+        self.environment.define_variable("this".to_string(), this.clone());
+        let value = self.evaluate_expression(default);
+        self.environment.pop_frame();
+        let value = value?;
+
+        if self.matches_type(&field.field_type, &value) {
+            Ok(value)
+        } else {
+            Err(format!(
+                "Type mismatch for field {}: expected {}, got {}",
+                field.name,
+                formatter::format_type(&field.field_type),
+                value.type_name()
+            ))
         }
     }
 
-    fn subtract_values(&self, left: Value, right: Value) -> Result<Value, String> {
-        match (left, right) {
-            (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(a - b)),
-            (left, right) => Err(format!(
-                "Cannot subtract {} and {}",
-                left.type_name(),
-                right.type_name()
-            )),
+    /// The error for a field an object doesn't have. A field its class
+    /// declares is only missing while a field initializer above it runs.
+    fn missing_field(&self, class_name: &str, field: &str) -> String {
+        let declared = self
+            .environment
+            .get_class(class_name)
+            .is_some_and(|class_def| class_def.fields.iter().any(|f| f.name == field));
+        if declared {
+            format!("Field {} is used before it is initialized", field)
+        } else {
+            format!("Field {} not found", field)
         }
     }
 
-    fn multiply_values(&self, left: Value, right: Value) -> Result<Value, String> {
-        match (left, right) {
-            (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(a * b)),
-            (left, right) => Err(format!(
-                "Cannot multiply {} and {}",
-                left.type_name(),
-                right.type_name()
-            )),
+    /// Defaults are evaluated at call time, after earlier parameters are bound.
+    fn evaluate_default(&mut self, param: &Parameter) -> Result<Value, String> {
+        match &param.default {
+            Some(default) => self.evaluate_expression(default),
+            None => unreachable!("missing arguments are reported before binding"),
         }
     }
 
-    fn divide_values(&self, left: Value, right: Value) -> Result<Value, String> {
-        match (left, right) {
-            (Value::Integer(a), Value::Integer(b)) => {
-                if b == 0 {
-                    Err("Division by zero".to_string())
+    fn check_argument_type(&self, param: &Parameter, value: &Value) -> Result<(), String> {
+        match (&param.param_type, value) {
+            (Type::Int, Value::Integer(_)) => Ok(()),
+            (Type::Float, Value::Float(_)) => Ok(()),
+            (Type::String, Value::String(_)) => Ok(()),
+            (Type::Char, Value::Char(_)) => Ok(()),
+            (Type::Map, Value::Map(_)) => Ok(()),
+            (Type::List, Value::List(_)) => Ok(()),
+            (Type::Function, Value::Function { .. }) => Ok(()),
+            (Type::MapOf(_), Value::Map(_)) | (Type::ListOf(_), Value::List(_)) => {
+                match self.mismatched_element(&param.param_type, value) {
+                    Some(element) => Err(format!(
+                        "Argument type mismatch for parameter {}: expected {}, got a {} containing {}",
+                        param.name,
+                        formatter::format_type(&param.param_type),
+                        value.type_name(),
+                        element.type_name()
+                    )),
+                    None => Ok(()),
+                }
+            }
+            (Type::Void, Value::Void) => Ok(()),
+            (Type::Custom(class_name), Value::Object { class_name: obj_class, .. }) => {
+                if self.is_instance_of(obj_class, class_name) {
+                    Ok(())
                 } else {
-                    Ok(Value::Integer(a / b))
+                    Err(format!(
+                        "Argument type mismatch for parameter {}: expected {}, got {}",
+                        param.name, class_name, obj_class
+                    ))
                 }
             }
-            (left, right) => Err(format!(
-                "Cannot divide {} and {}",
-                left.type_name(),
-                right.type_name()
+            _ => Err(format!(
+                "Argument type mismatch for parameter {}: expected {}, got {}",
+                param.name,
+                formatter::format_type(&param.param_type),
+                value.type_name()
             )),
         }
     }
 
-    fn equal_values(&self, left: Value, right: Value) -> Result<Value, String> {
-        let result = match (left, right) {
-            (Value::Integer(a), Value::Integer(b)) => a == b,
-            (Value::String(a), Value::String(b)) => a == b,
+    /// For a `List<T>` or `Map<T>` value, finds the first element that isn't a
+    /// `T`, checking nested collections all the way down.
+    fn mismatched_element<'a>(&self, type_: &Type, value: &'a Value) -> Option<&'a Value> {
+        match (type_, value) {
+            (Type::ListOf(element_type), Value::List(items)) => {
+                items.iter().find(|item| !self.matches_type(element_type, item))
+            }
+            (Type::MapOf(value_type), Value::Map(entries)) => {
+                entries.values().find(|entry| !self.matches_type(value_type, entry))
+            }
+            _ => None,
+        }
+    }
+
+    fn matches_type(&self, type_: &Type, value: &Value) -> bool {
+        match (type_, value) {
+            (Type::Int, Value::Integer(_))
+            | (Type::Float, Value::Float(_))
+            | (Type::String, Value::String(_))
+            | (Type::Char, Value::Char(_))
+            | (Type::Map, Value::Map(_))
+            | (Type::List, Value::List(_))
+            | (Type::Function, Value::Function { .. })
+            | (Type::Void, Value::Void) => true,
+            (Type::MapOf(_), Value::Map(_)) | (Type::ListOf(_), Value::List(_)) => {
+                self.mismatched_element(type_, value).is_none()
+            }
+            (Type::Custom(type_name), Value::Object { class_name, .. }) => self.is_instance_of(class_name, type_name),
             _ => false,
-        };
-        Ok(Value::Integer(if result { 1 } else { 0 }))
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lexer::Lexer;
-    use crate::parser::Parser;
+    /// An object matches a custom type if it is of that class or its class
+    /// lists that interface after `implements`.
+    fn is_instance_of(&self, class_name: &str, type_name: &str) -> bool {
+        if class_name == type_name {
+            return true;
+        }
+        match self.environment.get_class(class_name) {
+            Some(class_def) => class_def.implements.iter().any(|interface| interface == type_name),
+            None => false,
+        }
+    }
 
-    #[test]
-    fn test_variable_declaration_and_arithmetic() {
-        let source = r#"
-Int n1 = 1
-Int n2 = 2
-print(n1 + n2)
-"#;
+    /// Evaluates and type-checks the value of a variable or constant declaration.
+    /// The name must not already be a constant, class or interface.
+    fn evaluate_declaration(&mut self, name: &str, var_type: &Type, value: &Expression) -> Result<Value, String> {
+        if self.environment.is_constant(name) {
+            return Err(format!("Cannot assign to constant {}", name));
+        }
+        // A variable named after a type would make `Name.new()` ambiguous
+        if self.environment.get_class(name).is_some() {
+            return Err(format!("Variable {} conflicts with class {}", name, name));
+        }
+        if self.environment.get_interface(name).is_some() {
+            return Err(format!("Variable {} conflicts with interface {}", name, name));
+        }
+        if self.environment.is_module(name) {
+            return Err(format!("Variable {} conflicts with module {}", name, name));
+        }
 
-        let mut lexer = Lexer::new(source);
-        let tokens = lexer.tokenize().unwrap();
-        let mut parser = Parser::new(tokens);
-        let program = parser.parse().unwrap();
-        let mut interpreter = Interpreter::new();
+        self.check_type_defined(var_type)?;
+        let val = self.evaluate_expression(value)?;
 
-        let result = interpreter.interpret(program);
-        assert!(result.is_ok());
+        // Type checking
+        match (var_type, &val) {
+            (Type::Int, Value::Integer(_)) => {},
+            (Type::Float, Value::Float(_)) => {},
+            (Type::String, Value::String(_)) => {},
+            (Type::Char, Value::Char(_)) => {},
+            (Type::Map, Value::Map(_)) => {},
+            (Type::List, Value::List(_)) => {},
+            (Type::Function, Value::Function { .. }) => {},
+            (Type::MapOf(_), Value::Map(_)) | (Type::ListOf(_), Value::List(_)) => {
+                self.check_collection_type(var_type, &val)?;
+            },
+            (Type::Void, Value::Void) => {},
+            (Type::Custom(class_name), Value::Object { class_name: obj_class, .. }) => {
+                if !self.is_instance_of(obj_class, class_name) {
+                    return Err(format!(
+                        "Type mismatch: expected {}, got {}",
+                        class_name,
+                        obj_class
+                    ));
+                }
+            },
+            _ => return Err(format!(
+                "Type mismatch: expected {}, got {}",
+                formatter::format_type(var_type),
+                val.type_name()
+            )),
+        }
+
+        Ok(val)
+    }
+
+    /// Checks a value against a `List<T>` or `Map<T>` type, including the
+    /// type of every element.
+    fn check_collection_type(&self, collection_type: &Type, value: &Value) -> Result<(), String> {
+        match (collection_type, value) {
+            (Type::MapOf(_), Value::Map(_)) | (Type::ListOf(_), Value::List(_)) => {
+                match self.mismatched_element(collection_type, value) {
+                    Some(element) => Err(format!(
+                        "Type mismatch: expected {}, got a {} containing {}",
+                        formatter::format_type(collection_type),
+                        value.type_name(),
+                        element.type_name()
+                    )),
+                    None => Ok(()),
+                }
+            }
+            _ => Err(format!(
+                "Type mismatch: expected {}, got {}",
+                formatter::format_type(collection_type),
+                value.type_name()
+            )),
+        }
+    }
+
+    /// Assigns to a variable, field or element. Writing an element updates
+    /// the whole collection through its own target, so a variable declared
+    /// as `List<T>` or `Map<T>` checks element writes as well.
+    fn assign_to(&mut self, target: &Expression, value: Value) -> Result<(), String> {
+        match target {
+            Expression::Identifier(name) => {
+                if self.environment.is_constant(name) {
+                    return Err(format!("Cannot assign to constant {}", name));
+                }
+                if let Some(collection_type) = self.environment.collection_type(name) {
+                    self.check_collection_type(collection_type, &value)?;
+                }
+                self.environment.assign_variable(name.clone(), value);
+                Ok(())
+            }
+            Expression::Index { object, index } => {
+                // Update a copy of the container, then write it back through its own target
+                let container = self.evaluate_expression(object)?;
+                let key = self.evaluate_expression(index)?;
+
+                match (container, key) {
+                    (Value::Map(mut entries), Value::String(key)) => {
+                        entries.insert(key, value);
+                        self.assign_to(object, Value::Map(entries))
+                    }
+                    (Value::Map(_), key) => Err(format!(
+                        "Map keys must be String, got {}",
+                        key.type_name()
+                    )),
+                    (Value::List(mut elements), Value::Integer(index)) => {
+                        let position = resolve_index(index, elements.len())?;
+                        elements[position] = value;
+                        self.assign_to(object, Value::List(elements))
+                    }
+                    (container, _) => Err(format!(
+                        "Cannot assign by index into {}",
+                        container.type_name()
+                    )),
+                }
+            }
+            Expression::FieldAccess { optional: true, .. } => Err("Cannot assign through '?.'".to_string()),
+            Expression::FieldAccess { object, field, .. } => {
+                // Objects are shared, so setting the field is seen through every reference
+                match self.evaluate_expression(object)? {
+                    Value::Object { class_name, fields } => match fields.borrow_mut().get_mut(field) {
+                        Some(slot) => {
+                            *slot = value;
+                            Ok(())
+                        }
+                        None => Err(self.missing_field(&class_name, field)),
+                    },
+                    _ => Err("Cannot access field on non-object value".to_string()),
+                }
+            }
+            _ => Err("Only variable, field and index assignments are supported".to_string()),
+        }
     }
-}
\ No newline at end of file
+
+    fn evaluate_expression(&mut self, expression: &Expression) -> Result<Value, String> {
+        match expression {
+            Expression::Integer(n) => Ok(Value::Integer(*n)),
+
+            Expression::Float(n) => Ok(Value::Float(*n)),
+            Expression::Char(c) => Ok(Value::Char(*c)),
+
+            Expression::Map(entries) => {
+                let mut map = HashMap::new();
+                for (key_expr, value_expr) in entries {
+                    let key = match self.evaluate_expression(key_expr)? {
+                        Value::String(key) => key,
+                        other => return Err(format!(
+                            "Map keys must be String, got {}",
+                            other.type_name()
+                        )),
+                    };
+                    let value = self.evaluate_expression(value_expr)?;
+                    map.insert(key, value);
+                }
+                Ok(Value::Map(map))
+            }
+
+            Expression::List(elements) => {
+                let mut values = Vec::with_capacity(elements.len());
+                for element in elements {
+                    values.push(self.evaluate_expression(element)?);
+                }
+                Ok(Value::List(values))
+            }
+
+            Expression::Index { object, index } => {
+                let container = self.evaluate_expression(object)?;
+                let key = self.evaluate_expression(index)?;
+
+                match (container, key) {
+                    (Value::Map(entries), Value::String(key)) => {
+                        // Reading a missing key is an error; there is no nil value
+                        entries.get(&key)
+                            .cloned()
+                            .ok_or_else(|| format!("Key \"{}\" not found in map", key))
+                    }
+                    (Value::Map(_), key) => Err(format!(
+                        "Map keys must be String, got {}",
+                        key.type_name()
+                    )),
+                    (Value::List(elements), Value::Integer(index)) => {
+                        let position = resolve_index(index, elements.len())?;
+                        Ok(elements[position].clone())
+                    }
+                    (Value::String(s), Value::Integer(index)) => {
+                        let chars: Vec<char> = s.chars().collect();
+                        let position = resolve_index(index, chars.len())?;
+                        Ok(Value::String(chars[position].to_string()))
+                    }
+                    (container, _) => Err(format!(
+                        "Cannot index into {}",
+                        container.type_name()
+                    )),
+                }
+            }
+
+            Expression::String(s) => {
+                // Handle simple string interpolation #{variable}
+                if s.contains("#{") {
+                    let mut result = s.clone();
+                    // Simple regex-like replacement for #{name}
+                    if let Some(start) = result.find("#{") {
+                        if let Some(end) = result.find('}') {
+                            let var_name = &result[start + 2..end];
+                            if let Some(value) = self.environment.get_variable(var_name) {
+                                let replacement = value.to_string();
+                                result.replace_range(start..=end, &replacement);
+                            }
+                        }
+                    }
+                    Ok(Value::String(result))
+                } else {
+                    Ok(Value::String(s.clone()))
+                }
+            }
+
+            Expression::Identifier(name) => {
+                // A function's name, used without calling it, is the function itself
+                if let Some(value) = self.environment.get_variable(name).or_else(|| self.environment.get_function(name)) {
+                    Ok(value.clone())
+                } else {
+                    Err(format!("Undefined variable: {}", name))
+                }
+            }
+
+            Expression::Binary { left, operator, right } => {
+                let left_val = self.evaluate_expression(left)?;
+
+                // `and` and `or` only evaluate the right operand when it decides the result
+                if matches!(operator, BinaryOperator::And | BinaryOperator::Or) {
+                    let left_true = self.is_truthy(&left_val);
+                    let result = match operator {
+                        BinaryOperator::And if !left_true => false,
+                        BinaryOperator::Or if left_true => true,
+                        _ => {
+                            let right_val = self.evaluate_expression(right)?;
+                            self.is_truthy(&right_val)
+                        }
+                    };
+                    return Ok(Value::Integer(if result { 1 } else { 0 }));
+                }
+
+                let right_val = self.evaluate_expression(right)?;
+
+                match operator {
+                    BinaryOperator::Add => self.add_values(left_val, right_val),
+                    BinaryOperator::Subtract => self.subtract_values(left_val, right_val),
+                    BinaryOperator::Multiply => self.multiply_values(left_val, right_val),
+                    BinaryOperator::Divide => self.divide_values(left_val, right_val),
+                    BinaryOperator::IntDivide => self.int_divide_values(left_val, right_val),
+                    BinaryOperator::Modulo => self.modulo_values(left_val, right_val),
+                    BinaryOperator::Equal => self.equal_values(left_val, right_val),
+                    BinaryOperator::NotEqual => match self.equal_values(left_val, right_val)? {
+                        Value::Integer(equal) => Ok(Value::Integer(1 - equal)),
+                        _ => unreachable!("equal_values returns 1 or 0"),
+                    },
+                    BinaryOperator::And | BinaryOperator::Or => unreachable!("handled above"),
+                    BinaryOperator::Less
+                    | BinaryOperator::Greater
+                    | BinaryOperator::LessEqual
+                    | BinaryOperator::GreaterEqual => self.compare_values(operator, left_val, right_val),
+                    BinaryOperator::Power => self.power_values(left_val, right_val),
+                    BinaryOperator::BitAnd
+                    | BinaryOperator::BitOr
+                    | BinaryOperator::BitXor
+                    | BinaryOperator::ShiftLeft
+                    | BinaryOperator::ShiftRight => self.bitwise_values(operator, left_val, right_val),
+                }
+            }
+
+            Expression::Unary { operator, operand } => {
+                let value = self.evaluate_expression(operand)?;
+
+                match (operator, value) {
+                    (UnaryOperator::Negate, Value::Integer(n)) => n
+                        .checked_neg()
+                        .map(Value::Integer)
+                        .ok_or_else(|| format!("Integer overflow: -({})", n)),
+                    (UnaryOperator::Negate, Value::Float(n)) => Ok(Value::Float(-n)),
+                    (UnaryOperator::Negate, value) => Err(format!("Cannot negate {}", value.type_name())),
+                    (UnaryOperator::Not, value) => Ok(Value::Integer(if self.is_truthy(&value) { 0 } else { 1 })),
+                }
+            }
+
+            Expression::FunctionCall { name, arguments, keyword_arguments } => {
+                // A variable holding a function shadows a function of the same name
+                if let Some(value) = self.environment.get_variable(name) {
+                    return match value {
+                        Value::Function { .. } => self.call_function_value(value.clone(), arguments, keyword_arguments),
+                        other => Err(format!("Cannot call {} of type {}", name, other.type_name())),
+                    };
+                }
+                self.call_function(name, arguments, keyword_arguments)
+            }
+
+            Expression::StringInterpolation { parts: _ } => {
+                // TODO: Implement string interpolation
+                Ok(Value::String("".to_string()))
+            }
+
+            Expression::MethodCall { object, method, arguments, keyword_arguments, optional } => {
+                // A class or module name that isn't shadowed by a variable calls a
+                // static method or a function in the module
+                if let Some(name) = object.dotted_name() {
+                    let root = name.split('.').next().unwrap_or_default();
+                    if self.environment.get_variable(root).is_none() {
+                        if let Some(class_def) = self.environment.get_class(&name).cloned() {
+                            return self.call_static_method(&class_def, method, arguments, keyword_arguments);
+                        }
+                        if self.environment.is_module(&name) {
+                            return self.call_function(&format!("{}.{}", name, method), arguments, keyword_arguments);
+                        }
+                    }
+                }
+
+                let obj_value = self.evaluate_expression(object)?;
+                if *optional && obj_value == Value::Void {
+                    return Ok(Value::Void);
+                }
+
+                if let Value::Object { class_name, .. } = &obj_value {
+                    if let Some(class_def) = self.environment.get_class(class_name).cloned() {
+                        let Some(method_def) = self.find_method(&class_def, method) else {
+                            return Err(format!("Method {} not found in class {}", method, class_name));
+                        };
+                        if method_def.is_static {
+                            return Err(format!(
+                                "Static method {} must be called on the class, as {}.{}()",
+                                method, class_name, method
+                            ));
+                        }
+
+                        // Check argument count
+                        let arg_values = self.evaluate_arguments(
+                            "Method",
+                            method,
+                            &method_def.parameters,
+                            arguments,
+                            keyword_arguments,
+                        )?;
+
+                        for param in &method_def.parameters {
+                            self.check_type_defined(&param.param_type)?;
+                        }
+                        self.check_type_defined(&method_def.return_type)?;
+
+                        self.call(
+                            &format!("{}.{}", class_name, method),
+                            &method_def.parameters,
+                            arg_values,
+                            &method_def.return_type,
+                            &method_def.body,
+                            Some(obj_value.clone()),
+                        )
+                    } else {
+                        Err(format!("Class {} not found", class_name))
+                    }
+                } else {
+                    Err("Cannot call method on non-object value".to_string())
+                }
+            }
+
+            Expression::FieldAccess { object, field, optional } => {
+                let obj_value = self.evaluate_expression(object)?;
+                if *optional && obj_value == Value::Void {
+                    return Ok(Value::Void);
+                }
+
+                if let Value::Object { class_name, fields } = obj_value {
+                    if let Some(field_value) = fields.borrow().get(field) {
+                        Ok(field_value.clone())
+                    } else {
+                        Err(self.missing_field(&class_name, field))
+                    }
+                } else {
+                    Err("Cannot access field on non-object value".to_string())
+                }
+            }
+
+            Expression::ObjectCreation { class_name } => {
+                if let Some(class_def) = self.environment.get_class(class_name).cloned() {
+                    let fields = Rc::new(RefCell::new(HashMap::new()));
+                    let object = Value::Object {
+                        class_name: class_name.clone(),
+                        fields: Rc::clone(&fields),
+                    };
+
+                    // Initialize fields in the order they are declared, from their
+                    // initializers or with default values, so an initializer can use
+                    // the fields above it through `this`
+                    for field in &class_def.fields {
+                        let value = match &field.default {
+                            Some(default) => self.evaluate_field_default(field, default, &object)?,
+                            None => match &field.field_type {
+                                Type::Int => Value::Integer(0),
+                                Type::Float => Value::Float(0.0),
+                                Type::String => Value::String("".to_string()),
+                                Type::Char => Value::Char('\0'),
+                                Type::Map | Type::MapOf(_) => Value::Map(HashMap::new()),
+                                Type::List | Type::ListOf(_) => Value::List(Vec::new()),
+                                Type::Void | Type::Function | Type::Custom(_) => Value::Void,
+                            },
+                        };
+                        fields.borrow_mut().insert(field.name.clone(), value);
+                    }
+
+                    // TODO: Handle constructor arguments
+                    Ok(object)
+                } else {
+                    Err(format!("Class {} not found", class_name))
+                }
+            }
+        }
+    }
+
+    fn add_values(&self, left: Value, right: Value) -> Result<Value, String> {
+        if let Some((a, b)) = float_operands(&left, &right) {
+            return Ok(Value::Float(a + b));
+        }
+
+        match (left, right) {
+            (Value::Integer(a), Value::Integer(b)) => checked_int(a.checked_add(b), a, "+", b),
+            (Value::String(a), Value::String(b)) => Ok(Value::String(a + &b)),
+            (Value::String(a), Value::Char(b)) => Ok(Value::String(format!("{}{}", a, b))),
+            (Value::Char(a), Value::String(b)) => Ok(Value::String(format!("{}{}", a, b))),
+            (Value::Char(a), Value::Char(b)) => Ok(Value::String(format!("{}{}", a, b))),
+            (left, right) => Err(format!(
+                "Cannot add {} and {}",
+                left.type_name(),
+                right.type_name()
+            )),
+        }
+    }
+
+    fn subtract_values(&self, left: Value, right: Value) -> Result<Value, String> {
+        if let Some((a, b)) = float_operands(&left, &right) {
+            return Ok(Value::Float(a - b));
+        }
+
+        match (left, right) {
+            (Value::Integer(a), Value::Integer(b)) => checked_int(a.checked_sub(b), a, "-", b),
+            (left, right) => Err(format!(
+                "Cannot subtract {} and {}",
+                left.type_name(),
+                right.type_name()
+            )),
+        }
+    }
+
+    fn multiply_values(&self, left: Value, right: Value) -> Result<Value, String> {
+        if let Some((a, b)) = float_operands(&left, &right) {
+            return Ok(Value::Float(a * b));
+        }
+
+        match (left, right) {
+            (Value::Integer(a), Value::Integer(b)) => checked_int(a.checked_mul(b), a, "*", b),
+            (Value::String(text), Value::Integer(count)) | (Value::Integer(count), Value::String(text)) => {
+                let Ok(times) = usize::try_from(count) else {
+                    return Err(format!("Cannot repeat a String a negative number of times ({})", count));
+                };
+                match text.len().checked_mul(times) {
+                    Some(len) if len <= MAX_REPEAT_LEN => Ok(Value::String(text.repeat(times))),
+                    _ => Err(format!("Cannot repeat a String {} times: the result would be too long", count)),
+                }
+            }
+            (left, right) => Err(format!(
+                "Cannot multiply {} and {}",
+                left.type_name(),
+                right.type_name()
+            )),
+        }
+    }
+
+    /// `/` always produces a Float, even for two Int operands; use `//` or
+    /// `intdiv` for truncating integer division.
+    fn divide_values(&self, left: Value, right: Value) -> Result<Value, String> {
+        let (a, b) = match (&left, &right) {
+            (Value::Integer(a), Value::Integer(b)) => (*a as f64, *b as f64),
+            _ => match float_operands(&left, &right) {
+                Some(operands) => operands,
+                None => return Err(format!(
+                    "Cannot divide {} and {}",
+                    left.type_name(),
+                    right.type_name()
+                )),
+            },
+        };
+
+        if b == 0.0 {
+            Err("Division by zero".to_string())
+        } else {
+            Ok(Value::Float(a / b))
+        }
+    }
+
+    fn int_divide_values(&self, left: Value, right: Value) -> Result<Value, String> {
+        match (left, right) {
+            (Value::Integer(_), Value::Integer(0)) => Err("Division by zero".to_string()),
+            (Value::Integer(a), Value::Integer(b)) => checked_int(a.checked_div(b), a, "//", b),
+            (left, right) => Err(format!(
+                "Cannot integer-divide {} and {}",
+                left.type_name(),
+                right.type_name()
+            )),
+        }
+    }
+
+    /// `%` is the remainder of truncating division, so it takes the sign of
+    /// the left operand (`-7 % 2` is `-1`).
+    fn modulo_values(&self, left: Value, right: Value) -> Result<Value, String> {
+        match (left, right) {
+            (Value::Integer(_), Value::Integer(0)) => Err("Division by zero".to_string()),
+            (Value::Integer(a), Value::Integer(b)) => checked_int(a.checked_rem(b), a, "%", b),
+            (left, right) => Err(format!(
+                "Cannot take the remainder of {} and {}",
+                left.type_name(),
+                right.type_name()
+            )),
+        }
+    }
+
+    /// Two Ints with a non-negative exponent give an Int; a negative exponent
+    /// or any Float operand gives a Float, so `2 ** -1` is `0.5`.
+    fn power_values(&self, left: Value, right: Value) -> Result<Value, String> {
+        match (&left, &right) {
+            (Value::Integer(base), Value::Integer(exponent)) if *exponent >= 0 => u32::try_from(*exponent)
+                .ok()
+                .and_then(|exponent| base.checked_pow(exponent))
+                .map(Value::Integer)
+                .ok_or_else(|| format!("Integer overflow computing {} ** {}", base, exponent)),
+            (Value::Integer(base), Value::Integer(exponent)) => {
+                Ok(Value::Float((*base as f64).powf(*exponent as f64)))
+            }
+            _ => match float_operands(&left, &right) {
+                Some((base, exponent)) => Ok(Value::Float(base.powf(exponent))),
+                None => Err(format!(
+                    "Cannot raise {} to the power of {}",
+                    left.type_name(),
+                    right.type_name()
+                )),
+            },
+        }
+    }
+
+    /// Bitwise operators only apply to two Ints. Shifting by a negative amount
+    /// or by 64 or more bits is an error.
+    fn bitwise_values(&self, operator: &BinaryOperator, left: Value, right: Value) -> Result<Value, String> {
+        let (a, b) = match (&left, &right) {
+            (Value::Integer(a), Value::Integer(b)) => (*a, *b),
+            _ => return Err(format!(
+                "Bitwise operators require Int operands, got {} and {}",
+                left.type_name(),
+                right.type_name()
+            )),
+        };
+
+        let shift_amount = || {
+            u32::try_from(b)
+                .ok()
+                .filter(|&bits| bits < i64::BITS)
+                .ok_or_else(|| format!("Shift amount {} out of range", b))
+        };
+
+        let result = match operator {
+            BinaryOperator::BitAnd => a & b,
+            BinaryOperator::BitOr => a | b,
+            BinaryOperator::BitXor => a ^ b,
+            BinaryOperator::ShiftLeft => a << shift_amount()?,
+            BinaryOperator::ShiftRight => a >> shift_amount()?,
+            _ => unreachable!("not a bitwise operator"),
+        };
+        Ok(Value::Integer(result))
+    }
+
+    /// Equality is structural: lists compare element-wise, maps entry-wise, and
+    /// objects are equal when they are the same object or of the same class
+    /// with equal fields. Mixed Int/Float operands compare numerically at the
+    /// top level.
+    fn equal_values(&self, left: Value, right: Value) -> Result<Value, String> {
+        Ok(Value::Integer(if values_equal(&left, &right) { 1 } else { 0 }))
+    }
+
+    /// `<`, `>`, `<=` and `>=` order numbers (mixing Int and Float), Strings
+    /// lexicographically and Chars by code point; other pairs are an error.
+    fn compare_values(&self, operator: &BinaryOperator, left: Value, right: Value) -> Result<Value, String> {
+        let ordering = match (&left, &right) {
+            (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (Value::Char(a), Value::Char(b)) => Some(a.cmp(b)),
+            _ => match float_operands(&left, &right) {
+                Some((a, b)) => a.partial_cmp(&b),
+                None => return Err(format!(
+                    "Cannot compare {} and {}",
+                    left.type_name(),
+                    right.type_name()
+                )),
+            },
+        };
+
+        // NaN is unordered, so every comparison with it is false
+        let result = ordering.is_some_and(|ordering| match operator {
+            BinaryOperator::Less => ordering.is_lt(),
+            BinaryOperator::Greater => ordering.is_gt(),
+            BinaryOperator::LessEqual => ordering.is_le(),
+            BinaryOperator::GreaterEqual => ordering.is_ge(),
+            _ => unreachable!(),
+        });
+        Ok(Value::Integer(if result { 1 } else { 0 }))
+    }
+}
+
+/// `==` on two values. Numbers compare by value, so `1 == 1.0`, and that
+/// holds inside Lists, Maps and objects too: two objects are equal when they
+/// are the same object, or of the same class with equal fields.
+fn values_equal(left: &Value, right: &Value) -> bool {
+    values_equal_within(left, right, &mut Vec::new())
+}
+
+/// Pairs of objects whose fields are being compared further up, so an
+/// object that refers back to itself doesn't recurse forever.
+type Comparing = Vec<(*const RefCell<HashMap<String, Value>>, *const RefCell<HashMap<String, Value>>)>;
+
+fn values_equal_within(left: &Value, right: &Value, comparing: &mut Comparing) -> bool {
+    match (left, right) {
+        (Value::List(a), Value::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(x, y)| values_equal_within(x, y, comparing))
+        }
+        (Value::Map(a), Value::Map(b)) => fields_equal(a, b, comparing),
+        (
+            Value::Object { class_name: a_class, fields: a_fields },
+            Value::Object { class_name: b_class, fields: b_fields },
+        ) => {
+            if Rc::ptr_eq(a_fields, b_fields) {
+                return true;
+            }
+            if a_class != b_class {
+                return false;
+            }
+            // A pair already being compared is equal unless some other field says otherwise
+            let pair = (Rc::as_ptr(a_fields), Rc::as_ptr(b_fields));
+            if comparing.contains(&pair) {
+                return true;
+            }
+            comparing.push(pair);
+            let equal = fields_equal(&a_fields.borrow(), &b_fields.borrow(), comparing);
+            comparing.pop();
+            equal
+        }
+        _ => match float_operands(left, right) {
+            Some((a, b)) => a == b,
+            None => left == right,
+        },
+    }
+}
+
+fn fields_equal(a: &HashMap<String, Value>, b: &HashMap<String, Value>, comparing: &mut Comparing) -> bool {
+    a.len() == b.len()
+        && a.iter().all(|(key, value)| b.get(key).is_some_and(|other| values_equal_within(value, other, comparing)))
+}
+
+/// Parses `toInt`'s argument: an optional `+` or `-` and one or more ASCII
+/// digits, with any whitespace around them.
+fn parse_int(text: &str) -> Result<i64, String> {
+    text.trim().parse::<i64>().map_err(|err| match err.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+            format!("toInt(\"{}\") is out of the Int range", text)
+        }
+        _ => format!("toInt() can't convert \"{}\" to an Int", text),
+    })
+}
+
+/// Copies a value, including every object it reaches, so the copy shares no
+/// fields with the original. `copies` maps each object already copied to its
+/// copy, so an object reached twice is copied once and cycles are kept.
+fn deep_copy(value: &Value, copies: &mut HashMap<*const RefCell<HashMap<String, Value>>, Value>) -> Value {
+    match value {
+        Value::List(elements) => Value::List(elements.iter().map(|element| deep_copy(element, copies)).collect()),
+        Value::Map(entries) => Value::Map(
+            entries.iter().map(|(key, value)| (key.clone(), deep_copy(value, copies))).collect(),
+        ),
+        Value::Object { class_name, fields } => {
+            if let Some(copy) = copies.get(&Rc::as_ptr(fields)) {
+                return copy.clone();
+            }
+            let copied_fields = Rc::new(RefCell::new(HashMap::new()));
+            let copy = Value::Object {
+                class_name: class_name.clone(),
+                fields: Rc::clone(&copied_fields),
+            };
+            copies.insert(Rc::as_ptr(fields), copy.clone());
+
+            let entries: Vec<(String, Value)> =
+                fields.borrow().iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+            for (key, value) in entries {
+                let value = deep_copy(&value, copies);
+                copied_fields.borrow_mut().insert(key, value);
+            }
+            copy
+        }
+        other => other.clone(),
+    }
+}
+
+/// Shows a Float rounded to 15 significant digits, which hides binary
+/// rounding noise (`0.1 + 0.2` shows as `0.3`), with no trailing zeros but
+/// always a decimal point for whole numbers, so `3.0` doesn't look like an Int.
+fn format_float(n: f64) -> String {
+    if !n.is_finite() {
+        return n.to_string();
+    }
+
+    let rounded: f64 = format!("{:.14e}", n).parse().unwrap_or(n);
+    let text = rounded.to_string();
+    if text.contains('.') {
+        text
+    } else {
+        format!("{}.0", text)
+    }
+}
+
+/// Wraps the result of a checked Int operation, reporting overflow instead of
+/// panicking or wrapping around.
+fn checked_int(result: Option<i64>, a: i64, operator: &str, b: i64) -> Result<Value, String> {
+    result
+        .map(Value::Integer)
+        .ok_or_else(|| format!("Integer overflow: {} {} {}", a, operator, b))
+}
+
+/// Splits out the top-level `const` declarations whose values can be worked
+/// out before the program runs, keeping them in source order, from the
+/// statements left to run in order. A declaration split out of a multiple
+/// declaration leaves the rest of it in place. Classes, interfaces and
+/// modules are still defined when their statement runs, so a constant named
+/// like one of them waits for its line, where the name conflict is reported.
+fn hoist_constants(statements: Vec<Statement>) -> (Vec<Statement>, Vec<Statement>) {
+    let definitions: HashSet<String> = statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::ClassDefinition { name, .. }
+            | Statement::InterfaceDefinition { name, .. }
+            | Statement::Module { name, .. } => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut known = HashSet::new();
+    let mut hoist = |declaration: &Statement| match declaration {
+        Statement::ConstDeclaration { name, value, .. }
+            if !definitions.contains(name) && is_constant_expression(value, &known) =>
+        {
+            known.insert(name.clone());
+            true
+        }
+        _ => false,
+    };
+
+    let mut constants = Vec::new();
+    let mut rest = Vec::with_capacity(statements.len());
+    for statement in statements {
+        match statement {
+            Statement::MultipleDeclaration { declarations, span } => {
+                let (hoisted, remaining): (Vec<_>, Vec<_>) = declarations.into_iter().partition(|d| hoist(d));
+                constants.extend(hoisted);
+                if !remaining.is_empty() {
+                    rest.push(Statement::MultipleDeclaration { declarations: remaining, span });
+                }
+            }
+            statement if hoist(&statement) => constants.push(statement),
+            statement => rest.push(statement),
+        }
+    }
+    (constants, rest)
+}
+
+/// Whether an expression always has the same value, whenever it runs: it
+/// is built from literals and operators, and only names constants in
+/// `constants`. Calls and interpolated strings are never constant.
+fn is_constant_expression(expression: &Expression, constants: &HashSet<String>) -> bool {
+    match expression {
+        Expression::Integer(_) | Expression::Float(_) | Expression::Char(_) => true,
+        Expression::String(s) => !s.contains("#{"),
+        Expression::Identifier(name) => constants.contains(name),
+        Expression::Binary { left, right, .. } => {
+            is_constant_expression(left, constants) && is_constant_expression(right, constants)
+        }
+        Expression::Unary { operand, .. } => is_constant_expression(operand, constants),
+        Expression::List(elements) => elements.iter().all(|element| is_constant_expression(element, constants)),
+        Expression::Map(entries) => entries
+            .iter()
+            .all(|(key, value)| is_constant_expression(key, constants) && is_constant_expression(value, constants)),
+        Expression::Index { object, index } => {
+            is_constant_expression(object, constants) && is_constant_expression(index, constants)
+        }
+        Expression::FunctionCall { .. }
+        | Expression::MethodCall { .. }
+        | Expression::FieldAccess { .. }
+        | Expression::ObjectCreation { .. }
+        | Expression::StringInterpolation { .. } => false,
+    }
+}
+
+/// Resolves a possibly negative index against a sequence length. Negative
+/// indices count from the end, so `-1` is the last element.
+fn resolve_index(index: i64, len: usize) -> Result<usize, String> {
+    let resolved = if index < 0 { index + len as i64 } else { index };
+    if resolved < 0 || resolved >= len as i64 {
+        Err(format!("Index {} out of range for length {}", index, len))
+    } else {
+        Ok(resolved as usize)
+    }
+}
+
+/// Returns both operands as floats when at least one is a Float and the other
+/// is numeric, so mixed Int/Float arithmetic promotes to Float.
+fn float_operands(left: &Value, right: &Value) -> Option<(f64, f64)> {
+    match (left, right) {
+        (Value::Float(a), Value::Float(b)) => Some((*a, *b)),
+        (Value::Integer(a), Value::Float(b)) => Some((*a as f64, *b)),
+        (Value::Float(a), Value::Integer(b)) => Some((*a, *b as f64)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A `Write` sink that tests can read back after the interpreter is done.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_source(source: &str) -> Result<Interpreter, String> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().map_err(|errors| errors[0].to_string())?;
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().map_err(|errors| errors[0].to_string())?;
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(program)?;
+        Ok(interpreter)
+    }
+
+    fn run_and_capture(source: &str) -> Result<String, String> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().map_err(|errors| errors[0].to_string())?;
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().map_err(|errors| errors[0].to_string())?;
+
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::with_output(Box::new(buffer.clone()));
+        interpreter.interpret(program)?;
+
+        let output = buffer.0.borrow();
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    #[test]
+    fn test_variable_declaration_and_arithmetic() {
+        let source = r#"
+Int n1 = 1
+Int n2 = 2
+print(n1 + n2)
+"#;
+
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+        let mut interpreter = Interpreter::new();
+
+        let result = interpreter.interpret(program);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_map_literal_index_and_assignment() {
+        let source = r#"
+Map m = { "a": 1, "b": 2 }
+m["a"] = 3
+m["c"] = 4
+Int a = m["a"]
+Int size = len(m)
+"#;
+
+        let interpreter = run_source(source).unwrap();
+        assert_eq!(interpreter.environment.get_variable("a"), Some(&Value::Integer(3)));
+        assert_eq!(interpreter.environment.get_variable("size"), Some(&Value::Integer(3)));
+    }
+
+    #[test]
+    fn test_map_missing_key_is_error() {
+        let result = run_source("Map m = { \"a\": 1 }\nprint(m[\"z\"])");
+        assert_eq!(result.err(), Some("Key \"z\" not found in map at line 2".to_string()));
+    }
+
+    #[test]
+    fn test_division_produces_float() {
+        let source = r#"
+Float half = 7 / 2
+Int truncated = 7 // 2
+Int viaBuiltin = intdiv(7, 2)
+Float mixed = 1.5 + 1
+"#;
+
+        let interpreter = run_source(source).unwrap();
+        assert_eq!(interpreter.environment.get_variable("half"), Some(&Value::Float(3.5)));
+        assert_eq!(interpreter.environment.get_variable("truncated"), Some(&Value::Integer(3)));
+        assert_eq!(interpreter.environment.get_variable("viaBuiltin"), Some(&Value::Integer(3)));
+        assert_eq!(interpreter.environment.get_variable("mixed"), Some(&Value::Float(2.5)));
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        assert_eq!(run_source("print(1 / 0)").err(), Some("Division by zero at line 1".to_string()));
+        assert_eq!(run_source("print(1 // 0)").err(), Some("Division by zero at line 1".to_string()));
+        assert_eq!(run_source("print(1 % 0)").err(), Some("Division by zero at line 1".to_string()));
+        assert_eq!(run_source("print(intdiv(1, 0))").err(), Some("Division by zero at line 1".to_string()));
+    }
+
+    #[test]
+    fn test_integer_division_overflow() {
+        let output = run_and_capture("Int min = -9223372036854775807 - 1\nprint(min // 1, 7 % 3, -7 % 2, 7 % -2)").unwrap();
+        assert_eq!(output, "-9223372036854775808 1 -1 1\n");
+
+        assert_eq!(
+            run_source("Int min = -9223372036854775807 - 1\nprint(min // -1)").err(),
+            Some("Integer overflow: -9223372036854775808 // -1 at line 2".to_string())
+        );
+        assert_eq!(
+            run_source("Int min = -9223372036854775807 - 1\nprint(intdiv(min, -1))").err(),
+            Some("Integer overflow: -9223372036854775808 // -1 at line 2".to_string())
+        );
+        assert_eq!(
+            run_source("Int min = -9223372036854775807 - 1\nprint(min % -1)").err(),
+            Some("Integer overflow: -9223372036854775808 % -1 at line 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unknown_type_in_declaration() {
+        let result = run_source("Foo x = 1");
+        assert_eq!(result.err(), Some("Unknown type Foo at line 1".to_string()));
+    }
+
+    #[test]
+    fn test_unknown_type_in_parameter() {
+        let source = r#"
+def show(thing: Widget) -> Int
+  1
+end
+show(1)
+"#;
+        assert_eq!(run_source(source).err(), Some("Unknown type Widget at line 5".to_string()));
+    }
+
+    #[test]
+    fn test_custom_type_declaration_accepts_class_and_interface() {
+        let source = r#"
+interface Greetable
+  def greet() : String
+end
+class Person implements Greetable
+  String name
+end
+Person p = Person.new()
+Greetable g = Person.new()
+"#;
+        assert!(run_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_const_declaration() {
+        let interpreter = run_source("const Int MAX = 10\nInt doubled = MAX * 2").unwrap();
+        assert_eq!(interpreter.environment.get_variable("doubled"), Some(&Value::Integer(20)));
+    }
+
+    #[test]
+    fn test_const_reassignment_is_rejected() {
+        let result = run_source("const Int MAX = 10\nMAX = 11");
+        assert_eq!(result.err(), Some("Cannot assign to constant MAX at line 2".to_string()));
+
+        let result = run_source("const Int MAX = 10\nInt MAX = 11");
+        assert_eq!(result.err(), Some("Cannot assign to constant MAX at line 2".to_string()));
+    }
+
+    #[test]
+    fn test_const_type_mismatch() {
+        let result = run_source("const Int MAX = \"ten\"");
+        assert_eq!(result.err(), Some("Type mismatch: expected Int, got String at line 1".to_string()));
+    }
+
+    #[test]
+    fn test_nested_field_read_and_write() {
+        let source = r#"
+class Inner
+  Int value
+end
+class Outer
+  Inner inner
+end
+Outer obj = Outer.new()
+obj.inner = Inner.new()
+obj.inner.value = 5
+Int result = obj.inner.value
+"#;
+
+        let interpreter = run_source(source).unwrap();
+        assert_eq!(interpreter.environment.get_variable("result"), Some(&Value::Integer(5)));
+    }
+
+    #[test]
+    fn test_assigning_unknown_field_is_error() {
+        let source = "class Empty\n  Int x\nend\nEmpty e = Empty.new()\ne.y = 1";
+        assert_eq!(run_source(source).err(), Some("Field y not found at line 5".to_string()));
+    }
+
+    #[test]
+    fn test_write_omits_newline() {
+        let output = run_and_capture("write(\"a\")\nwrite(1)\nprint(\"b\")").unwrap();
+        assert_eq!(output, "a1b\n");
+    }
+
+    #[test]
+    fn test_print_multiple_arguments() {
+        let output = run_and_capture("Int a = 1\nString b = \"two\"\nFloat c = 3.5\nprint(a, b, c)\nprint()").unwrap();
+        assert_eq!(output, "1 two 3.5\n\n");
+    }
+
+    #[test]
+    fn test_runtime_error_reports_innermost_line() {
+        let source = r#"
+def broken() -> Int
+  print(missing)
+end
+
+broken()
+"#;
+        assert_eq!(run_source(source).err(), Some("Undefined variable: missing at line 3".to_string()));
+    }
+
+    #[test]
+    fn test_negative_string_index() {
+        let interpreter = run_source("String s = \"hello\"\nString last = s[-1]\nString first = s[0]").unwrap();
+        assert_eq!(interpreter.environment.get_variable("last"), Some(&Value::String("o".to_string())));
+        assert_eq!(interpreter.environment.get_variable("first"), Some(&Value::String("h".to_string())));
+    }
+
+    #[test]
+    fn test_negative_index_out_of_range() {
+        let result = run_source("String s = \"hello\"\nprint(s[-10])");
+        assert_eq!(result.err(), Some("Index -10 out of range for length 5 at line 2".to_string()));
+    }
+
+    #[test]
+    fn test_range_builds_lists() {
+        let source = r#"
+List xs = range(5)
+List ys = range(2, 5)
+Int last = xs[-1]
+Int size = len(ys)
+"#;
+        let interpreter = run_source(source).unwrap();
+        let expected: Vec<Value> = (0..5).map(Value::Integer).collect();
+        assert_eq!(interpreter.environment.get_variable("xs"), Some(&Value::List(expected)));
+        assert_eq!(interpreter.environment.get_variable("last"), Some(&Value::Integer(4)));
+        assert_eq!(interpreter.environment.get_variable("size"), Some(&Value::Integer(3)));
+    }
+
+    #[test]
+    fn test_range_rejects_non_integers() {
+        let result = run_source("print(range(\"5\"))");
+        assert_eq!(result.err(), Some("range() expects Int arguments, got String at line 1".to_string()));
+    }
+
+    #[test]
+    fn test_range_rejects_huge_spans() {
+        assert_eq!(
+            run_source("print(range(0, 9223372036854775807))").err(),
+            Some("range(0, 9223372036854775807) would have more than 16777216 elements at line 1".to_string())
+        );
+        assert_eq!(
+            run_source("print(range(-9223372036854775807, 9223372036854775807))").err(),
+            Some("range(-9223372036854775807, 9223372036854775807) would have more than 16777216 elements at line 1".to_string())
+        );
+        assert_eq!(run_and_capture("print(len(range(5, -9223372036854775807)))").unwrap(), "0\n");
+    }
+
+    #[test]
+    fn test_list_literal_and_index_assignment() {
+        let output = run_and_capture("List xs = [1, 2, 3]\nxs[-1] = 30\nprint(xs)").unwrap();
+        assert_eq!(output, "[1, 2, 30]\n");
+    }
+
+    #[test]
+    fn test_collection_display() {
+        let source = r#"
+class Node
+  Node next
+end
+Node n = Node.new()
+n.next = n
+print(["a, b", 'c', 1, 2.0, [], {}], "top", 'd')
+print({"k": "v", "list": [{"x": 'y'}], "node": n, "lines": "a
+b"})
+print([n, n.next, print])
+"#;
+        assert_eq!(
+            run_and_capture(source).unwrap(),
+            "[\"a, b\", 'c', 1, 2.0, [], {}] top d\n\
+             {\"k\": \"v\", \"lines\": \"a\\nb\", \"list\": [{\"x\": 'y'}], \"node\": <Node object>}\n\
+             [<Node object>, <Node object>, <function print>]\n"
+        );
+    }
+
+    #[test]
+    fn test_structural_equality() {
+        let source = r#"
+class Point
+  Int x
+end
+Point a = Point.new()
+Point b = Point.new()
+Int sameObjects = a == b
+b.x = 1
+Int differentObjects = a == b
+Int sameLists = [1, [2, "three"]] == [1, [2, "three"]]
+Int differentLists = [1, 2] == [2, 1]
+Int sameMaps = { "k": [1] } == { "k": [1] }
+"#;
+        let interpreter = run_source(source).unwrap();
+        let get = |name: &str| interpreter.environment.get_variable(name).cloned();
+        assert_eq!(get("sameObjects"), Some(Value::Integer(1)));
+        assert_eq!(get("differentObjects"), Some(Value::Integer(0)));
+        assert_eq!(get("sameLists"), Some(Value::Integer(1)));
+        assert_eq!(get("differentLists"), Some(Value::Integer(0)));
+        assert_eq!(get("sameMaps"), Some(Value::Integer(1)));
+    }
+
+    #[test]
+    fn test_type_builtin() {
+        let source = r#"
+class Person
+  String name
+end
+print(type(1), type(1.5), type("s"), type([1]), type({ "a": 1 }))
+print(type(Person.new()))
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "Int Float String List Map\nPerson\n");
+    }
+
+    #[test]
+    fn test_type_predicates() {
+        let source = r#"
+class Person
+  String name
+end
+print(isInt(1), isInt(1.5), isInt("1"), isInt('1'))
+print(isString("s"), isString('s'), isString([]), isString(1))
+print(isObject(Person.new()), isObject({ "a": 1 }), isObject("Person"))
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "1 0 0 0\n1 0 0 0\n1 0 0\n");
+
+        assert_eq!(
+            run_source("isInt(1, 2)").err(),
+            Some("isInt() takes exactly one argument at line 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_type_mismatch_reports_class_name() {
+        let source = "class Person\n  String name\nend\nInt n = Person.new()";
+        assert_eq!(
+            run_source(source).err(),
+            Some("Type mismatch: expected Int, got Person at line 4".to_string())
+        );
+    }
+
+    #[test]
+    fn test_default_parameters() {
+        let source = r#"
+def greet(name: String, greeting: String = "Hello") -> String
+  greeting + ", " + name
+end
+print(greet("Ada"))
+print(greet("Ada", "Hi"))
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "Hello, Ada\nHi, Ada\n");
+
+        let source = "def greet(name: String, greeting: String = \"Hello\") -> String\n  greeting\nend\ngreet()";
+        assert_eq!(
+            run_source(source).err(),
+            Some("Function greet expects 1 to 2 arguments, got 0 at line 4".to_string())
+        );
+    }
+
+    #[test]
+    fn test_keyword_arguments() {
+        let source = r#"
+def greet(name: String, greeting: String = "Hello", punctuation: String = "!") -> String
+  greeting + ", " + name + punctuation
+end
+class Greeter
+  def greet(name: String, greeting: String) : String
+    greeting + " " + name
+  end
+end
+print(greet(greeting: "Hi", name: "Bob"))
+print(greet("Ada", punctuation: "?"))
+print(Greeter.new().greet(greeting: "Hey", name: "Cy"))
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "Hi, Bob!\nHello, Ada?\nHey Cy\n");
+
+        let define = "def greet(name: String, greeting: String = \"Hello\") -> String\n  greeting\nend\n";
+        let cases = [
+            ("greet(nme: \"Bob\")", "Function greet has no parameter named nme"),
+            ("greet(\"Bob\", name: \"Al\")", "Argument name is given more than once"),
+            ("greet(greeting: \"Hi\")", "Function greet is missing argument name"),
+            ("print(value: 1)", "print() does not take keyword arguments"),
+        ];
+        for (call, message) in cases {
+            assert_eq!(run_source(&format!("{}{}", define, call)).err(), Some(format!("{} at line 4", message)));
+        }
+    }
+
+    #[test]
+    fn test_variadic_parameters() {
+        let source = r#"
+def sum_from(nums: List, i: Int) -> Int
+  if i == len(nums)
+    0
+  else
+    nums[i] + sum_from(nums, i + 1)
+  end
+end
+def sum(nums: Int...) -> Int
+  sum_from(nums, 0)
+end
+def rest(label: String, nums: Int...) -> List
+  nums
+end
+print(sum(), sum(5), sum(1, 2, 3, 4))
+print(rest("a"), rest("a", 1, 2))
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "0 5 10\n[] [1, 2]\n");
+
+        let source = "def f(nums: Int...) -> Int\n  0\nend\nf(1, \"two\")";
+        assert_eq!(
+            run_source(source).err(),
+            Some("Argument type mismatch for parameter nums: expected Int, got String at line 4".to_string())
+        );
+    }
+
+    #[test]
+    fn test_empty_program() {
+        for source in ["", "\n\n", "# nothing to see\n   \n# here"] {
+            assert_eq!(run_and_capture(source).unwrap(), "");
+        }
+
+        let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+        let program = Parser::new(Lexer::new("  \n").tokenize().unwrap()).parse().unwrap();
+        assert_eq!(interpreter.interpret_with_result(program), Ok(None));
+    }
+
+    #[test]
+    fn test_while_with_break_and_continue() {
+        let source = r#"
+Int i = 0
+Int total = 0
+while 1
+  i = i + 1
+  if i == 3
+    continue
+  end
+  if i == 6
+    break
+  end
+  total = total + i
+end
+"#;
+        let interpreter = run_source(source).unwrap();
+        assert_eq!(interpreter.environment.get_variable("i"), Some(&Value::Integer(6)));
+        assert_eq!(interpreter.environment.get_variable("total"), Some(&Value::Integer(12)));
+    }
+
+    #[test]
+    fn test_while_condition_is_evaluated_each_iteration() {
+        let source = r#"
+class Counter
+  Int count = 0
+end
+Counter counter = Counter.new()
+def tick() -> Int
+  counter.count = counter.count + 1
+  return counter.count < 4
+end
+Int runs = 0
+while tick()
+  Int step = runs + 1
+  runs = step
+end
+print(counter.count, runs)
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "4 3\n");
+
+        // Variables declared in the body don't outlive the iteration, so the condition can't see them
+        let source = "Int i = 0\nwhile i < 1 or seen\n  Int seen = 0\n  i = i + 1\nend";
+        assert_eq!(run_source(source).err(), Some("Undefined variable: seen at line 2".to_string()));
+    }
+
+    #[test]
+    fn test_loop_bodies_discard_expression_values() {
+        let source = r#"
+def inc(n: Int) -> Int
+  n + 1
+end
+Int i = 0
+while i < 3
+  inc(i)
+  i = i + 1
+end
+print(i)
+def count_to(n: Int) -> Int
+  Int total = 0
+  repeat
+    total = inc(total)
+    inc(total)
+  until total == n
+  while 1
+    if total == n
+      return total * 10
+    end
+    inc(total)
+  end
+end
+print(count_to(4))
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "3\n40\n");
+    }
+
+    #[test]
+    fn test_match_statement() {
+        let source = r#"
+def describe(x: Int) -> String
+  match x
+    case 1
+      "one"
+    case 1 + 1
+      "two"
+    default
+      "many"
+  end
+end
+print(describe(1), describe(2), describe(3))
+String kind = ""
+match 2.0
+  case 2
+    kind = "numeric"
+end
+print(kind)
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "one two many\nnumeric\n");
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let output = run_and_capture("print(6 & 3, 6 | 3, 6 ^ 3, 1 << 4, -16 >> 2)\nprint(1 | 2 == 3, 1 + 1 << 1)").unwrap();
+        assert_eq!(output, "2 7 5 16 -4\n1 4\n");
+
+        assert_eq!(
+            run_source("print(1.5 & 1)").err(),
+            Some("Bitwise operators require Int operands, got Float and Int at line 1".to_string())
+        );
+        assert_eq!(
+            run_source("print(1 << 64)").err(),
+            Some("Shift amount 64 out of range at line 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let output = run_and_capture(
+            "print(1 and 2, 1 and 0, 0 or \"\", 0 or [1], not 0, not \"a\", 1 != 2, 2 != 2.0)",
+        )
+        .unwrap();
+        assert_eq!(output, "1 0 0 1 1 0 1 0\n");
+
+        // The right operand isn't evaluated when the left decides the result
+        assert_eq!(run_and_capture("print(0 and missing(), 1 or missing())").unwrap(), "0 1\n");
+        assert_eq!(
+            run_source("print(1 and missing())").err(),
+            Some("Undefined function: missing at line 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_associativity() {
+        let output = run_and_capture("print(2 - 3 - 4, 2 ** 3 ** 2, 100 // 10 // 5, 2 ** 2 ** 3 - 1 - 1)").unwrap();
+        assert_eq!(output, "-5 512 2 254\n");
+    }
+
+    #[test]
+    fn test_power_operator() {
+        let output = run_and_capture("print(2 ** 10, 2 ** 3 ** 2, -2 ** 2, (-2) ** 3)\nprint(2 ** -1, 4.0 ** 0.5, 2 * 3 ** 2)").unwrap();
+        assert_eq!(output, "1024 512 -4 -8\n0.5 2.0 18\n");
+
+        assert_eq!(
+            run_source("print(10 ** 19)").err(),
+            Some("Integer overflow computing 10 ** 19 at line 1".to_string())
+        );
+
+        // A negative exponent too large for 32 bits isn't truncated
+        let output = run_and_capture("print(2 ** -4294967297, 1 ** -4294967297, (-1) ** -4294967297)").unwrap();
+        assert_eq!(output, "0.0 1.0 -1.0\n");
+    }
+
+    #[test]
+    fn test_try_catch() {
+        let source = r#"
+def risky(n: Int) -> Int
+  Int local = 1
+  n // 0
+end
+String outer = ""
+String inner = ""
+try
+  try
+    risky(1)
+  catch e
+    inner = e
+    [][0]
+  end
+catch e
+  outer = e
+end
+"#;
+        let interpreter = run_source(source).unwrap();
+        let get = |name: &str| interpreter.environment.get_variable(name).cloned();
+        assert_eq!(get("inner"), Some(Value::String("Division by zero".to_string())));
+        assert_eq!(get("outer"), Some(Value::String("Index 0 out of range for length 0".to_string())));
+        assert_eq!(get("local"), None);
+    }
+
+    #[test]
+    fn test_raise() {
+        let source = r#"
+def withdraw(balance: Int, amount: Int) -> Int
+  if amount == 0
+    raise "Nothing to withdraw"
+  end
+  balance - amount
+end
+try
+  withdraw(10, 0)
+catch e
+  print("caught:", e)
+end
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "caught: Nothing to withdraw\n");
+
+        assert_eq!(
+            run_source("\nraise \"bad \" + \"input\"").err(),
+            Some("bad input at line 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_multiple_declarations() {
+        let source = "Int a = 1, b = a + 1\nconst String X = \"x\", Y = \"y\"";
+        let interpreter = run_source(source).unwrap();
+        let get = |name: &str| interpreter.environment.get_variable(name).cloned();
+        assert_eq!(get("a"), Some(Value::Integer(1)));
+        assert_eq!(get("b"), Some(Value::Integer(2)));
+        assert!(interpreter.environment.is_constant("Y"));
+
+        assert_eq!(
+            run_source("Int a = 1, b = \"two\"").err(),
+            Some("Type mismatch: expected Int, got String at line 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_assert_builtin() {
+        assert!(run_source("assert(1 == 1)\nassert([1], \"non-empty\")").is_ok());
+        assert_eq!(
+            run_source("Int x = 2\nassert(x == 3)").err(),
+            Some("Assertion failed at line 2".to_string())
+        );
+        assert_eq!(
+            run_source("assert(\"\", \"name is \" + \"empty\")").err(),
+            Some("Assertion failed: name is empty at line 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_char_literals() {
+        let source = r#"
+Char c = 'a'
+Char newline = '\n'
+print(c, type(c), c + "bc", "x" + c, c == 'a', c == "a")
+write('\'')
+write(newline)
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "a Char abc xa 1 0\n'\n");
+
+        assert_eq!(
+            run_source("Char c = \"a\"").err(),
+            Some("Type mismatch: expected Char, got String at line 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_variable_cannot_shadow_type_name() {
+        assert_eq!(
+            run_source("class Person\n  String name\nend\nInt Person = 1").err(),
+            Some("Variable Person conflicts with class Person at line 4".to_string())
+        );
+        assert_eq!(
+            run_source("interface Named\n  def name() : String\nend\nconst Int Named = 1").err(),
+            Some("Variable Named conflicts with interface Named at line 4".to_string())
+        );
+
+        // The check runs in the other direction too
+        assert_eq!(
+            run_source("Int Person = 1\nclass Person\n  String name\nend").err(),
+            Some("Class Person conflicts with variable Person at line 2".to_string())
+        );
+        assert_eq!(
+            run_source("const Int Named = 1\ninterface Named\n  def name() : String\nend").err(),
+            Some("Interface Named conflicts with variable Named at line 2".to_string())
+        );
+        assert_eq!(
+            run_source("Int Geo = 1\nmodule Geo\nend").err(),
+            Some("Module Geo conflicts with variable Geo at line 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_block_scoping() {
+        let source = r#"
+Int outer = 1
+Int i = 0
+if 1
+  Int inner = 2
+  outer = inner
+end
+while i == 0
+  Int loop_local = 3
+  i = 1
+end
+try
+  raise "oops"
+catch e
+  Int handled = 1
+end
+"#;
+        let interpreter = run_source(source).unwrap();
+        let get = |name: &str| interpreter.environment.get_variable(name).cloned();
+        assert_eq!(get("outer"), Some(Value::Integer(2)));
+        assert_eq!(get("inner"), None);
+        assert_eq!(get("loop_local"), None);
+        assert_eq!(get("e"), None);
+        assert_eq!(get("handled"), None);
+
+        assert_eq!(
+            run_source("if 1\n  Int x = 1\nend\nprint(x)").err(),
+            Some("Undefined variable: x at line 4".to_string())
+        );
+    }
+
+    #[test]
+    fn test_do_block_scoping() {
+        let source = r#"
+Int outer = 1
+do
+  Int scoped = 4
+  outer = scoped
+  do
+    Int nested = scoped + 1
+    outer = nested
+  end
+end
+"#;
+        let interpreter = run_source(source).unwrap();
+        let get = |name: &str| interpreter.environment.get_variable(name).cloned();
+        assert_eq!(get("outer"), Some(Value::Integer(5)));
+        assert_eq!(get("scoped"), None);
+        assert_eq!(get("nested"), None);
+
+        assert_eq!(
+            run_source("do\n  Int x = 1\nend\nprint(x)").err(),
+            Some("Undefined variable: x at line 4".to_string())
+        );
+    }
+
+    #[test]
+    fn test_empty_bodies() {
+        let source = r#"
+def nothing() -> void
+end
+class Empty
+  def noop() : void
+  end
+end
+if 1
+else
+end
+while 0
+end
+print(nothing(), type(Empty.new()), Empty.new().noop())
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "void Empty void\n");
+    }
+
+    #[test]
+    fn test_method_chaining_with_return_this() {
+        let source = r#"
+class Builder
+  Int a
+  Int b
+  def setA(value: Int) : Builder
+    this.a = value
+    return this
+  end
+  def setB(value: Int) : Builder
+    this.b = value
+    return this
+  end
+end
+Builder built = Builder.new().setA(1).setB(2)
+Builder other = Builder.new()
+other.setA(5)
+print(built.a, built.b, other.a)
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "1 2 5\n");
+    }
+
+    #[test]
+    fn test_method_call_on_function_result() {
+        let source = r#"
+class Person
+  String name = "Ann"
+  def greet() : String
+    return "Hi " + this.name
+  end
+end
+class Tally
+  Int made = 0
+end
+Tally tally = Tally.new()
+def makePerson() -> Person
+  tally.made = tally.made + 1
+  return Person.new()
+end
+print(makePerson().greet())
+print(makePerson().name, tally.made)
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "Hi Ann\nAnn 2\n");
+    }
+
+    #[test]
+    fn test_return_ends_function_early() {
+        let source = r#"
+def classify(n: Int) -> String
+  Int i = 0
+  while 1
+    if i == n
+      return "found"
+    end
+    i = i + 1
+  end
+end
+def nothing() -> void
+  return
+end
+print(classify(3), nothing())
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "found void\n");
+    }
+
+    #[test]
+    fn test_string_repetition() {
+        let output = run_and_capture("print(\"ab\" * 3, 2 * \"-\", \"x\" * 0 == \"\", 2 * 3)").unwrap();
+        assert_eq!(output, "ababab -- 1 6\n");
+
+        assert_eq!(
+            run_source("print(\"ab\" * -1)").err(),
+            Some("Cannot repeat a String a negative number of times (-1) at line 1".to_string())
+        );
+        assert_eq!(
+            run_source("print(\"ab\" * 9223372036854775807)").err(),
+            Some("Cannot repeat a String 9223372036854775807 times: the result would be too long at line 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_void_calls_do_not_end_blocks() {
+        let source = r#"
+def greet(name: String) -> String
+  print("greeting")
+  write("")
+  "Hello, " + name
+end
+Int count = 0
+if 1
+  print("a")
+  print("b")
+  count = 2
+end
+print(greet("Ada"), type(print("inner")), count)
+"#;
+        let output = run_and_capture(source).unwrap();
+        assert_eq!(output, "a\nb\ngreeting\ninner\nHello, Ada Void 2\n");
+    }
+
+    #[test]
+    fn test_only_the_last_value_in_a_block_is_its_result() {
+        let source = r#"
+def inc(n: Int) -> Int
+  n + 1
+end
+def pick(n: Int) -> String
+  match n
+    case 1
+      inc(n)
+      "one"
+    default
+      do
+        inc(n)
+        "other"
+      end
+  end
+end
+Int steps = 0
+if 1
+  inc(steps)
+  steps = steps + 1
+end
+match steps
+  case 1
+    inc(steps)
+    steps = steps + 1
+end
+do
+  inc(steps)
+  steps = steps + 1
+end
+try
+  inc(steps)
+  raise "oops"
+catch e
+  inc(steps)
+  steps = steps + 1
+end
+print(steps, pick(1), pick(2))
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "4 one other\n");
+
+        // A value that isn't the last statement of its branch can't be the result
+        let source = "def f(n: Int) -> Int\n  if n\n    n\n    Int m = n\n  else\n    0\n  end\nend";
+        assert_eq!(
+            run_source(source).err(),
+            Some("Function f can reach its end without returning a value at line 1, column 1".to_string())
+        );
+        let source = "def f(n: Int) -> Int\n  repeat\n    n\n  until n\nend";
+        assert!(run_source(source).is_err());
+    }
+
+    #[test]
+    fn test_integer_overflow_is_an_error() {
+        let output = run_and_capture("Int max = 9223372036854775807\nprint(max - 1 + 1, -max - 1)").unwrap();
+        assert_eq!(output, "9223372036854775807 -9223372036854775808\n");
+
+        assert_eq!(
+            run_source("Int max = 9223372036854775807\nprint(max + 1)").err(),
+            Some("Integer overflow: 9223372036854775807 + 1 at line 2".to_string())
+        );
+        assert_eq!(
+            run_source("print(-9223372036854775807 - 2)").err(),
+            Some("Integer overflow: -9223372036854775807 - 2 at line 1".to_string())
+        );
+        assert_eq!(
+            run_source("print(4611686018427387904 * 2)").err(),
+            Some("Integer overflow: 4611686018427387904 * 2 at line 1".to_string())
+        );
+        assert_eq!(
+            run_source("Int min = -9223372036854775807 - 1\nprint(-min)").err(),
+            Some("Integer overflow: -(-9223372036854775808) at line 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_comparison_operators() {
+        let output = run_and_capture(
+            "print(\"apple\" < \"banana\", \"b\" > \"abc\", \"a\" <= \"a\", \"Z\" >= \"a\")\n\
+             print(1 < 2, 2.5 > 3, 3 >= 3.0, 'a' < 'b', 1 + 1 < 3 == 1)",
+        )
+        .unwrap();
+        assert_eq!(output, "1 1 1 0\n1 0 1 1 1\n");
+
+        assert_eq!(
+            run_source("print(\"1\" < 2)").err(),
+            Some("Cannot compare String and Int at line 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_typed_collections() {
+        let source = r#"
+List<Int> xs = []
+Map<List<Int>> m = { "a": [1, 2] }
+def total(nums: List<Int>) -> Int
+  len(nums)
+end
+Int n = total([1, 2, 3])
+"#;
+        let interpreter = run_source(source).unwrap();
+        assert_eq!(interpreter.environment.get_variable("xs"), Some(&Value::List(vec![])));
+        assert_eq!(interpreter.environment.get_variable("n"), Some(&Value::Integer(3)));
+
+        assert_eq!(
+            run_source("List<Int> xs = [1, \"two\"]").err(),
+            Some("Type mismatch: expected List<Int>, got a List containing String at line 1".to_string())
+        );
+        assert_eq!(
+            run_source("Map<List<Int>> m = { \"a\": [1], \"b\": [1.5] }").err(),
+            Some("Type mismatch: expected Map<List<Int>>, got a Map containing List at line 1".to_string())
+        );
+        assert_eq!(
+            run_source("def f(xs: List<String>) -> Int\n  0\nend\nf([1])").err(),
+            Some("Argument type mismatch for parameter xs: expected List<String>, got a List containing Int at line 4".to_string())
+        );
+        assert_eq!(
+            run_source("List<Int> xs = 1").err(),
+            Some("Type mismatch: expected List<Int>, got Int at line 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_collection_types_are_kept_after_declaration() {
+        let source = r#"
+List<Int> xs = []
+xs = [1, 2]
+xs[0] = 5
+Map<List<Int>> m = {}
+m["a"] = [3]
+m["a"][0] = 4
+do
+  List xs = ["shadowed"]
+  xs[0] = "still untyped"
+end
+print(xs, m)
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "[5, 2] {\"a\": [4]}\n");
+
+        assert_eq!(
+            run_source("List<Int> xs = []\nxs = [\"a\"]").err(),
+            Some("Type mismatch: expected List<Int>, got a List containing String at line 2".to_string())
+        );
+        assert_eq!(
+            run_source("List<Int> xs = []\nxs = \"a\"").err(),
+            Some("Type mismatch: expected List<Int>, got String at line 2".to_string())
+        );
+        assert_eq!(
+            run_source("Map<Int> m = {}\nm[\"k\"] = \"v\"").err(),
+            Some("Type mismatch: expected Map<Int>, got a Map containing String at line 2".to_string())
+        );
+        assert_eq!(
+            run_source("Map<List<Int>> m = { \"a\": [1] }\nm[\"a\"][0] = 1.5").err(),
+            Some("Type mismatch: expected Map<List<Int>>, got a Map containing List at line 2".to_string())
+        );
+        assert_eq!(
+            run_source("def f(xs: List<Int>) -> Int\n  xs[0] = \"a\"\n  0\nend\nf([1])").err(),
+            Some("Type mismatch: expected List<Int>, got a List containing String at line 2".to_string())
+        );
+        assert_eq!(
+            run_source("def f(nums: Int...) -> Int\n  nums[0] = \"a\"\n  0\nend\nf(1)").err(),
+            Some("Type mismatch: expected List<Int>, got a List containing String at line 2".to_string())
+        );
+
+        // Element types must name a defined class or interface too
+        assert_eq!(run_source("List<Nope> xs = []").err(), Some("Unknown type Nope at line 1".to_string()));
+        assert_eq!(run_source("Map<List<Nope>> m = {}").err(), Some("Unknown type Nope at line 1".to_string()));
+    }
+
+    #[test]
+    fn test_call_frames() {
+        let source = r#"
+Int counter = 0
+def bump() -> void
+  counter = counter + 1
+end
+def fib(n: Int) -> Int
+  if n < 2
+    return n
+  end
+  fib(n - 1) + fib(n - 2)
+end
+bump()
+bump()
+Int result = fib(15)
+"#;
+        let interpreter = run_source(source).unwrap();
+        assert_eq!(interpreter.environment.get_variable("result"), Some(&Value::Integer(610)));
+        // A call's assignments to variables from outside it end with the call
+        assert_eq!(interpreter.environment.get_variable("counter"), Some(&Value::Integer(0)));
+        assert_eq!(interpreter.environment.call_depth(), 0);
+
+        // A function sees the variables of the code that called it, and its
+        // own writes to them until it returns
+        let source = r#"
+def peek() -> Int
+  local = local + 1
+  local
+end
+def caller() -> Int
+  Int local = 1
+  Int seen = peek()
+  seen * 10 + local
+end
+print(caller())
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "21\n");
+    }
+
+    #[test]
+    fn test_semicolon_separated_statements() {
+        let output = run_and_capture("Int a = 1; Int b = 2; print(a + b);\nprint(a);").unwrap();
+        assert_eq!(output, "3\n1\n");
+    }
+
+    #[test]
+    fn test_print_uses_to_string() {
+        let source = r#"
+class Person
+  String name
+
+  def toString() : String
+    "Person(" + this.name + ")"
+  end
+end
+class Plain
+end
+Person p = Person.new()
+p.name = "Ada"
+print(p, Plain.new())
+write(p)
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "Person(Ada) <Plain object>\nPerson(Ada)");
+    }
+
+    #[test]
+    fn test_static_methods() {
+        let source = r#"
+class MathUtil
+  Int calls
+
+  static def square(n: Int) : Int
+    n * n
+  end
+
+  def twice(n: Int) : Int
+    n * 2
+  end
+end
+print(MathUtil.square(7))
+print(MathUtil.square(n: 3))
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "49\n9\n");
+
+        let class = "class A\n  static def make() : Int\n    this\n  end\n  def get() : Int\n    1\n  end\nend\n";
+        assert_eq!(
+            run_source(&format!("{}A.get()", class)).err(),
+            Some("Method get of class A is not static at line 9".to_string())
+        );
+        assert_eq!(
+            run_source(&format!("{}A.new().make()", class)).err(),
+            Some("Static method make must be called on the class, as A.make() at line 9".to_string())
+        );
+        assert_eq!(
+            run_source(&format!("{}A.make()", class)).err(),
+            Some("Undefined variable: this at line 3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_field_initializers() {
+        let source = r#"
+Int start = 10
+class Counter
+  Int count = start * 2
+  String label
+  List<Int> history = [1, 2]
+end
+Counter a = Counter.new()
+a.count = a.count + 1
+Counter b = Counter.new()
+print(a.count, b.count, b.label == "", len(b.history))
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "21 20 1 2\n");
+
+        assert_eq!(
+            run_source("class A\n  Int n = \"x\"\nend\nA.new()").err(),
+            Some("Type mismatch for field n: expected Int, got String at line 4".to_string())
+        );
+    }
+
+    #[test]
+    fn test_field_initializers_use_earlier_fields() {
+        let source = r#"
+class Box
+  Int width = 3
+  Int height
+  Int area = this.width * (this.height + 2)
+  String label = "box " + this.describe()
+  def describe() : String
+    if this.area > 5
+      return "big"
+    end
+    return "small"
+  end
+end
+Box b = Box.new()
+print(b.area, b.label)
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "6 box big\n");
+
+        assert_eq!(
+            run_source("class A\n  Int total = this.n + 1\n  Int n = 1\nend\nA.new()").err(),
+            Some("Field n is used before it is initialized at line 5".to_string())
+        );
+        assert_eq!(
+            run_source("class A\n  Int n = this.missing\nend\nA.new()").err(),
+            Some("Field missing not found at line 4".to_string())
+        );
+        assert_eq!(
+            run_source("class A\n  Int n = this.get()\n  Int m = 2\n  def get() : Int\n    this.m\n  end\nend\nA.new()").err(),
+            Some("Field m is used before it is initialized at line 5".to_string())
+        );
+
+        // An initializer sees the variables where the object is created
+        let source = "class A\n  Int n = k + 1\nend\ndef make() -> Int\n  Int k = 41\n  A.new().n\nend\nprint(make())";
+        assert_eq!(run_and_capture(source).unwrap(), "42\n");
+    }
+
+    #[test]
+    fn test_optional_chaining() {
+        let source = r#"
+class Node
+  Int value = 1
+  Node next
+
+  def get() : Int
+    this.value
+  end
+end
+Node head = Node.new()
+print(head?.value, head.next?.value, head.next?.next?.value, head.next?.get(), head?.get())
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "1 void void void 1\n");
+
+        assert_eq!(
+            run_source("Int n = 1\nn?.value").err(),
+            Some("Cannot access field on non-object value at line 2".to_string())
+        );
+        assert_eq!(
+            run_source("class A\n  Int n\nend\nA a = A.new()\na?.n = 1").err(),
+            Some("Cannot assign through '?.' at line 5".to_string())
+        );
+    }
+
+    #[test]
+    fn test_repeat_until() {
+        let source = r#"
+Int i = 10
+repeat
+  print(i)
+  i = i + 1
+until i > 5
+Int n = 0
+repeat
+  n = n + 1
+  if n == 2
+    continue
+  end
+  if n == 4
+    break
+  end
+  print(n)
+until 0
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "10\n1\n3\n");
+    }
+
+    #[test]
+    fn test_missing_return_value() {
+        let source = "def f(n: Int) -> Int\n  if n > 0\n    return n\n  end\nend";
+        assert_eq!(
+            run_source(source).err(),
+            Some("Function f can reach its end without returning a value at line 1, column 1".to_string())
+        );
+
+        // Every branch returns, or the loop can only be left by returning
+        let source = r#"
+def sign(n: Int) -> Int
+  if n < 0
+    return -1
+  else
+    match n
+      case 0
+        return 0
+      default
+        raise "positive"
+    end
+  end
+end
+def first(n: Int) -> Int
+  while 1
+    n = n + 1
+    if n > 3
+      return n
+    end
+  end
+end
+print(sign(-5), first(0))
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "-1 4\n");
+
+        assert!(run_source("def f() -> Int\n  while 1\n    break\n  end\nend").is_err());
+
+        // An expression statement that turns out to be void is caught at run time
+        assert_eq!(
+            run_source("def f() -> Int\n  print(1)\nend\nf()").err(),
+            Some("Missing return value: expected Int at line 4".to_string())
+        );
+    }
+
+    #[test]
+    fn test_modules() {
+        let source = r#"
+module Geo
+  interface Shape
+    def area() : Int
+  end
+
+  class Square implements Geo.Shape
+    Int side = 2
+
+    def area() : Int
+      this.side * this.side
+    end
+
+    static def of(side: Int) : Geo.Square
+      Geo.Square s = Geo.Square.new()
+      s.side = side
+      s
+    end
+  end
+
+  def total(a: Geo.Shape, b: Geo.Shape) -> Int
+    a.area() + b.area()
+  end
+
+  module Units
+    def scale() -> Int
+      10
+    end
+  end
+end
+Geo.Shape small = Geo.Square.new()
+print(Geo.total(small, Geo.Square.of(3)), Geo.Units.scale(), type(small))
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "13 10 Geo.Square\n");
+
+        assert_eq!(
+            run_source("module Geo\nend\nGeo.missing()").err(),
+            Some("Undefined function: Geo.missing at line 3".to_string())
+        );
+        assert_eq!(
+            run_source("module Geo\nend\nInt Geo = 1").err(),
+            Some("Variable Geo conflicts with module Geo at line 3".to_string())
+        );
+        // Names inside a module are only reachable qualified
+        assert_eq!(
+            run_source("module Geo\n  class Point\n  end\nend\nPoint.new()").err(),
+            Some("Class Point not found at line 5".to_string())
+        );
+    }
+
+    #[test]
+    fn test_this_reads_its_own_writes() {
+        let source = r#"
+class Counter
+  Int n
+  List<Int> log
+
+  def bump() : void
+    this.n = this.n + 1
+  end
+
+  def run() : Int
+    this.n = 5
+    Int seen = this.n
+    this.bump()
+    this.log = [seen, this.n]
+    this.log[1] = this.log[1] * 10
+    this.log[1] + this.n
+  end
+
+  def fail() : void
+    this.n = 99
+    raise "boom"
+  end
+end
+Counter c = Counter.new()
+print(c.run(), c.n, c.log)
+try
+  c.fail()
+catch e
+  print(e, c.n)
+end
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "66 6 [5, 60]\nboom 99\n");
+    }
+
+    #[test]
+    fn test_objects_are_shared_references() {
+        let source = r#"
+class Person
+  String name = "Ann"
+  Person friend
+  def rename(name: String) : void
+    this.name = name
+  end
+end
+def renameTo(p: Person, name: String) -> void
+  p.name = name
+end
+Person p1 = Person.new()
+Person p2 = p1
+p2.name = "x"
+print(p1.name)
+renameTo(p2, "y")
+print(p1.name)
+List<Person> people = [p1, Person.new()]
+people[1].rename("z")
+people[0].friend = people[1]
+print(p2.friend.name, people[1] == p1, people[0] == p1)
+p1.friend.friend = p1
+print(p2.friend.friend.name, p1 == p1.friend.friend)
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "x\ny\nz 0 1\ny 1\n");
+    }
+
+    #[test]
+    fn test_print_and_new_are_not_keywords() {
+        let source = r#"
+class Person
+  String name = "Ann"
+end
+Int new = 2
+Function show = print
+show(new, Person.new().name)
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "2 Ann\n");
+    }
+
+    #[test]
+    fn test_exit() {
+        let source = r#"
+def finish(code: Int) -> void
+  try
+    exit(code)
+  catch e
+    print("caught", e)
+  end
+  print("after exit")
+end
+print("before")
+finish(3)
+print("never")
+"#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::with_output(Box::new(buffer.clone()));
+        assert_eq!(interpreter.interpret(program), Err("Program exited with code 3 at line 4".to_string()));
+        assert_eq!(interpreter.exit_code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&buffer.0.borrow()), "before\n");
+
+        // Running again clears the code
+        interpreter.interpret(Parser::new(Lexer::new("print(1)").tokenize().unwrap()).parse().unwrap()).unwrap();
+        assert_eq!(interpreter.exit_code(), None);
+
+        for (call, message) in [
+            ("exit(256)", "exit() expects a code from 0 to 255, got 256"),
+            ("exit(-1)", "exit() expects a code from 0 to 255, got -1"),
+            ("exit(\"1\")", "exit() expects an Int, got String"),
+        ] {
+            let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+            let program = Parser::new(Lexer::new(call).tokenize().unwrap()).parse().unwrap();
+            assert_eq!(interpreter.interpret(program), Err(format!("{} at line 1", message)));
+            assert_eq!(interpreter.exit_code(), None);
+        }
+    }
+
+    #[test]
+    fn test_clone() {
+        let source = r#"
+class Node
+  String name
+  Node next
+  List<Int> tags
+end
+Node a = Node.new()
+a.name = "a"
+a.next = Node.new()
+a.next.name = "b"
+a.next.next = a
+Node copy = clone(a)
+a.name = "changed"
+a.next.name = "changed too"
+a.tags = [1]
+print(copy.name, copy.next.name, copy.tags, copy == a)
+copy.next.next.name = "cycle"
+print(copy.name, a.name)
+List<Node> nodes = clone([a, a])
+nodes[0].name = "shared"
+print(nodes[1].name, clone(5), clone("s"))
+"#;
+        assert_eq!(
+            run_and_capture(source).unwrap(),
+            "a b [] 0\ncycle changed\nshared 5 s\n"
+        );
+
+        let result = run_source("clone()");
+        assert_eq!(result.err(), Some("clone() takes exactly one argument at line 1".to_string()));
+    }
+
+    #[test]
+    fn test_object_equality() {
+        let source = r#"
+class Point
+  Int x
+  Float y
+  List tags
+end
+class Other
+  Int x
+  Float y
+  List tags
+end
+Point a = Point.new()
+Point b = Point.new()
+if a == b
+  print("equal")
+end
+b.x = 1
+print(a == b, a != b)
+a.x = 1
+a.y = 2.0
+b.y = 2
+a.tags = [1, [2.0]]
+b.tags = [1.0, [2]]
+print(a == b, a == Other.new(), {"p": a} == {"p": b})
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "equal\n0 1\n1 0 1\n");
+    }
+
+    #[test]
+    fn test_float_formatting() {
+        let output = run_and_capture("print(1.5, 3.0, 0.1 + 0.2, 7 / 2, 2 ** -1, -0.5 * 4, 1.0 / 3)").unwrap();
+        assert_eq!(output, "1.5 3.0 0.3 3.5 0.5 -2.0 0.333333333333333\n");
+
+        assert_eq!(format_float(1e-20), "0.00000000000000000001");
+        assert_eq!(format_float(f64::INFINITY), "inf");
+    }
+
+    #[test]
+    fn test_rounding_functions() {
+        let output = run_and_capture(
+            "print(floor(2.7), ceil(2.2), round(2.4), floor(-2.5), ceil(-2.5), round(2.5), round(-2.5), round(7))",
+        )
+        .unwrap();
+        assert_eq!(output, "2 3 2 -3 -2 3 -3 7\n");
+
+        assert_eq!(
+            run_source("floor(\"2.5\")").err(),
+            Some("floor() expects a number, got String at line 1".to_string())
+        );
+        assert_eq!(
+            run_source("round(10.0 ** 19)").err(),
+            Some("round(10000000000000000000.0) is out of the Int range at line 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_to_int() {
+        let output = run_and_capture(
+            "print(toInt(\"42\"), toInt(\" +42 \"), toInt(\"\t-7\n\"), toInt(\"007\"), toInt(-3), toInt(\"-9223372036854775808\"))",
+        )
+        .unwrap();
+        assert_eq!(output, "42 42 -7 7 -3 -9223372036854775808\n");
+
+        for bad in ["1.5", "abc", "", "  ", "+", "+-1", "1 2", "4_2", "0x10", "1e3"] {
+            assert_eq!(
+                run_source(&format!("toInt(\"{}\")", bad)).err(),
+                Some(format!("toInt() can't convert \"{}\" to an Int at line 1", bad))
+            );
+        }
+        assert_eq!(
+            run_source("toInt(\"9223372036854775808\")").err(),
+            Some("toInt(\"9223372036854775808\") is out of the Int range at line 1".to_string())
+        );
+        assert_eq!(
+            run_source("toInt(1.5)").err(),
+            Some("toInt() expects a String or Int, got Float at line 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_class_implements_several_interfaces() {
+        let source = r#"
+interface Walker
+  def walk() : String
+end
+interface Swimmer
+  def swim() : String
+end
+class Duck implements Walker, Swimmer
+  def walk() : String
+    "waddle"
+  end
+  def swim() : String
+    "paddle"
+  end
+end
+Walker w = Duck.new()
+Swimmer s = Duck.new()
+print(w.walk(), s.swim())
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "waddle paddle\n");
+
+        let interpreter = run_source(source.replace("print(w.walk(), s.swim())", "").as_str()).unwrap();
+        assert!(interpreter.environment.describe().contains("  Duck implements Walker, Swimmer"));
+    }
+
+    #[test]
+    fn test_interface_default_methods() {
+        let source = r#"
+interface Named
+  def name() : String
+  def greet(greeting: String = "Hello") : String
+    greeting + ", " + this.name()
+  end
+  def toString() : String
+    "<" + this.name() + ">"
+  end
+end
+interface Loud
+  def greet(greeting: String) : String
+    "HEY"
+  end
+end
+class Person implements Named, Loud
+  String first = "Ada"
+  def name() : String
+    this.first
+  end
+end
+class Robot implements Named
+  def name() : String
+    "R2"
+  end
+  def greet(greeting: String = "Beep") : String
+    greeting
+  end
+end
+Person p = Person.new()
+print(p.greet(), p.greet("Hi"), Robot.new().greet(), p)
+"#;
+        assert_eq!(run_and_capture(source).unwrap(), "Hello, Ada Hi, Ada Beep <Ada>\n");
+
+        assert_eq!(
+            run_source("interface A\n  def f() : Int\nend\nclass B implements A\nend\nB.new().f()").err(),
+            Some("Method f not found in class B at line 6".to_string())
+        );
+    }
+
+    fn run_and_trace(source: &str) -> (Result<(), String>, String) {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+
+        let trace = SharedBuffer::default();
+        let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+        interpreter.trace_to(Box::new(trace.clone()));
+        let result = interpreter.interpret(program);
+
+        let trace = trace.0.borrow();
+        (result, String::from_utf8_lossy(&trace).into_owned())
+    }
+
+    #[test]
+    fn test_trace() {
+        let source = r#"def double(n: Int) -> Int
+  return n * 2
+end
+class Counter
+  Int count = 0
+  def bump(by: Int) : void
+    this.count = this.count + by
+  end
+end
+Counter c = Counter.new()
+c.bump(double(3))"#;
+        let (result, trace) = run_and_trace(source);
+        assert!(result.is_ok());
+        assert_eq!(
+            trace,
+            "[trace] 1:1: def double(n: Int) -> Int\n\
+             [trace] 4:1: class Counter\n\
+             [trace] 10:1: Counter c = Counter.new()\n\
+             [trace] 11:1: c.bump(double(3))\n\
+             [trace]   -> double(n = 3)\n\
+             [trace]   2:3: return n * 2\n\
+             [trace]   <- double = 6\n\
+             [trace]   -> Counter.bump(by = 6)\n\
+             [trace]   7:5: this.count = this.count + by\n\
+             [trace]   <- Counter.bump\n"
+        );
+
+        let (result, trace) = run_and_trace("def fail() -> void\n  raise \"boom\"\nend\nfail()");
+        assert!(result.is_err());
+        assert!(trace.ends_with("[trace]   <- fail failed: boom\n"), "{}", trace);
+    }
+
+    #[test]
+    fn test_no_trace_by_default() {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::with_output(Box::new(buffer.clone()));
+        let tokens = Lexer::new("print(1)").tokenize().unwrap();
+        interpreter.interpret(Parser::new(tokens).parse().unwrap()).unwrap();
+        assert_eq!(String::from_utf8_lossy(&buffer.0.borrow()), "1\n");
+        assert!(interpreter.trace.is_none());
+    }
+
+    /// Runs `source` on a thread with the same large stack `main` uses, for
+    /// tests that recurse all the way to the call depth limit.
+    fn run_deep(source: String) -> Result<String, String> {
+        std::thread::Builder::new()
+            .stack_size(256 * 1024 * 1024)
+            .spawn(move || run_and_capture(&source))
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_recursion_depth_limit() {
+        let err = run_deep("def forever(n: Int) -> Int\n  return forever(n + 1)\nend\nforever(0)".to_string()).unwrap_err();
+        assert_eq!(err, "Maximum recursion depth exceeded (1000 calls) in forever at line 2");
+
+        // Methods count towards the same limit, and recursion below it still works
+        let walker = r#"class Walker
+  def down(n: Int) : Int
+    if n == 0
+      return 0
+    end
+    return this.down(n - 1) + 1
+  end
+end
+Walker w = Walker.new()
+"#;
+        assert_eq!(run_deep(format!("{}print(w.down(900))", walker)), Ok("900\n".to_string()));
+        let err = run_deep(format!("{}print(w.down(5000))", walker)).unwrap_err();
+        assert_eq!(err, "Maximum recursion depth exceeded (1000 calls) in Walker.down at line 6");
+    }
+
+    #[test]
+    fn test_functions_as_values() {
+        let source = r#"def double(n: Int) -> Int
+  return n * 2
+end
+def apply(f: Function, n: Int) -> Int
+  return f(n)
+end
+Function g = double
+print(apply(g, 4), apply(double, 5), g(n: 1))
+print(g, type(g))
+Function p = print
+p("via", "print")
+Function h = g
+h = len
+print(h([1, 2, 3]))"#;
+        assert_eq!(
+            run_and_capture(source).unwrap(),
+            "8 10 2\n<function double> Function\nvia print\n3\n"
+        );
+    }
+
+    #[test]
+    fn test_function_value_errors() {
+        let err = run_and_capture("Int n = 3\nn(1)").unwrap_err();
+        assert_eq!(err, "Cannot call n of type Int at line 2");
+
+        let err = run_and_capture("Function f = 3").unwrap_err();
+        assert_eq!(err, "Type mismatch: expected Function, got Int at line 1");
+
+        let err = run_and_capture("def f(g: Function) -> void\nend\nf(1)").unwrap_err();
+        assert_eq!(err, "Argument type mismatch for parameter g: expected Function, got Int at line 3");
+
+        let err = run_and_capture("Function p = print\np(values: 1)").unwrap_err();
+        assert_eq!(err, "print() does not take keyword arguments at line 2");
+    }
+
+    #[test]
+    fn test_map_filter_reduce() {
+        let source = r#"def double(n: Int) -> Int
+  return n * 2
+end
+def isEven(n: Int) -> Int
+  return n % 2 == 0
+end
+def add(total: Int, n: Int) -> Int
+  return total + n
+end
+List xs = [1, 2, 3, 4]
+print(map(xs, double))
+print(filter(xs, isEven))
+print(reduce(xs, add, 0), reduce(map(xs, double), add, 100))
+print(map(["a", "bcd"], len), reduce([], add, 7))"#;
+        assert_eq!(run_and_capture(source).unwrap(), "[2, 4, 6, 8]\n[2, 4]\n10 120\n[1, 3] 7\n");
+    }
+
+    #[test]
+    fn test_map_filter_reduce_errors() {
+        let functions = "def add(a: Int, b: Int) -> Int\n  return a + b\nend\n";
+        let cases = [
+            ("map([1], 3)", "map() expects a Function as its second argument, got Int"),
+            ("map([1], add)", "map() expects a function that takes 1 argument, but add takes 2"),
+            ("reduce([1], len, 0)", "reduce() expects a function that takes 2 arguments, but len takes 1"),
+            ("filter(3, add)", "filter() expects a List, got Int"),
+            ("reduce([1], add)", "reduce() takes exactly three arguments"),
+            ("map([\"a\"], round)", "round() expects a number, got String"),
+        ];
+        for (call, message) in cases {
+            let err = run_and_capture(&format!("{}{}", functions, call)).unwrap_err();
+            assert_eq!(err, format!("{} at line 4", message));
+        }
+    }
+
+    #[test]
+    fn test_eprint_writes_to_error_output() {
+        let tokens = Lexer::new("eprint(\"a\", 1)\nprint(\"out\")\neprintln(\"!\")\neprintln()").tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+
+        let output = SharedBuffer::default();
+        let error_output = SharedBuffer::default();
+        let mut interpreter = Interpreter::with_outputs(Box::new(output.clone()), Box::new(error_output.clone()));
+        interpreter.interpret(program).unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output.0.borrow()), "out\n");
+        assert_eq!(String::from_utf8_lossy(&error_output.0.borrow()), "a 1!\n\n");
+    }
+
+    #[test]
+    fn test_unicode_strings() {
+        let source = r#"String café = "naïve 😀!"
+Char ñ = '🎉'
+print(len(café), café[4], café[6], café[-1], café[-2])
+print(ñ + café, len("" + ñ), len(map(["é", "ab"], len)))
+String résumé = café
+print(résumé == "naïve 😀!", "é" < "😀")"#;
+        assert_eq!(
+            run_and_capture(source).unwrap(),
+            "8 e 😀 ! 😀\n🎉naïve 😀! 1 2\n1 1\n"
+        );
+
+        let err = run_and_capture("String s = \"😀\"\nprint(s[1])").unwrap_err();
+        assert_eq!(err, "Index 1 out of range for length 1 at line 2");
+    }
+
+    #[test]
+    fn test_brace_blocks_run() {
+        let source = r##"def fizz(n: Int) -> String {
+  if n % 15 == 0 { return "FizzBuzz" } elif n % 3 == 0 { return "Fizz" } else { return "#{n}" }
+}
+Int i = 1
+while i <= 3 { write(fizz(i) + " "); i = i + 1 }
+print(fizz(15))"##;
+        assert_eq!(run_and_capture(source).unwrap(), "1 2 Fizz FizzBuzz\n");
+    }
+
+    #[test]
+    fn test_constants_are_defined_before_the_program_runs() {
+        let source = r#"def area(r: Float) -> Float
+  return PI * r * r
+end
+print(area(1.0), LIMIT, NAMES[1])
+const Float PI = 3.5
+const Int BASE = 10, LIMIT = BASE * 2 + 1
+const List NAMES = ["a", "b"]
+print(PI)"#;
+        assert_eq!(run_and_capture(source).unwrap(), "3.5 21 b\n3.5\n");
+    }
+
+    #[test]
+    fn test_only_constant_values_are_hoisted() {
+        // A call, a variable or a constant declared further down all wait for their line
+        for (source, message) in [
+            ("print(N)\nconst Int N = len([1])", "Undefined variable: N at line 1"),
+            ("Int base = 1\nprint(N)\nconst Int N = base", "Undefined variable: N at line 2"),
+            ("print(B)\nconst Int B = A\nconst Int A = 1", "Undefined variable: B at line 1"),
+            ("print(N)\nInt N = 1", "Undefined variable: N at line 1"),
+        ] {
+            assert_eq!(run_and_capture(source).unwrap_err(), message);
+        }
+        assert_eq!(
+            run_and_capture("const Int B = A\nconst Int A = 1\nprint(A, B)").unwrap(),
+            "1 1\n"
+        );
+
+        // A hoisted constant that fails stops the program before anything runs
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::with_output(Box::new(buffer.clone()));
+        let tokens = Lexer::new("print(1)\nconst Int N = 1 // 0").tokenize().unwrap();
+        let err = interpreter.interpret(Parser::new(tokens).parse().unwrap()).unwrap_err();
+        assert_eq!(err, "Division by zero at line 2");
+        assert!(buffer.0.borrow().is_empty());
+    }
+}
@@ -1,33 +1,73 @@
+use crate::error::ExlineError;
 use std::fmt;
+use std::num::IntErrorKind;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     // Literals
     Integer(i64),
+    Float(f64),
     String(String),
+    Char(char),
     Identifier(String),
 
     // Keywords
     Int,
+    Float_,   // Float keyword
     String_,  // String keyword
+    Char_,    // Char keyword
     Def,
+    Const,
     End,
     If,
     Else,
-    Print,
+    Elif,
+    While,
+    Do,
+    Repeat,
+    Until,
+    Break,
+    Continue,
+    Match,
+    Case,
+    Default,
+    Try,
+    Catch,
+    Raise,
+    Return,
+    And,
+    Or,
+    Not,
     Class,
     Interface,
     Implements,
-    New,
+    Static,
+    Module,
     Void,
+    Map,
+    List,
+    Function,
 
     // Operators
     Plus,
     Minus,
     Multiply,
+    Power,      // **
     Divide,
+    IntDivide,  // //
+    Modulo,     // %
     Assign,
     Equal,
+    NotEqual,     // !=
+    Less,         // <
+    Greater,      // >
+    LessEqual,    // <=
+    GreaterEqual, // >=
+    Ampersand,  // &
+    Pipe,       // |
+    Caret,      // ^
+    ShiftLeft,  // <<
+    ShiftRight, // >>
 
     // Delimiters
     LeftParen,
@@ -35,14 +75,18 @@ pub enum TokenType {
     Arrow,      // ->
     Colon,      // :
     Dot,        // .
+    QuestionDot, // ?.
+    Ellipsis,   // ...
+    Comma,      // ,
+    Semicolon,  // ;
+    LeftBrace,  // {
+    RightBrace, // }
+    LeftBracket,  // [
+    RightBracket, // ]
 
     // Special
     Newline,
     Eof,
-
-    // String interpolation
-    InterpolationStart,  // #{
-    InterpolationEnd,    // }
 }
 
 #[derive(Debug, Clone)]
@@ -50,14 +94,20 @@ pub struct Token {
     pub token_type: TokenType,
     pub line: usize,
     pub column: usize,
+    /// Position just past the token's last character.
+    pub end_line: usize,
+    pub end_column: usize,
 }
 
 impl Token {
+    /// Creates a token whose end is filled in once it has been fully lexed.
     pub fn new(token_type: TokenType, line: usize, column: usize) -> Self {
         Self {
             token_type,
             line,
             column,
+            end_line: line,
+            end_column: column,
         }
     }
 }
@@ -73,6 +123,8 @@ pub struct Lexer {
     position: usize,
     line: usize,
     column: usize,
+    /// Set once `Eof` or an error has been yielded.
+    finished: bool,
 }
 
 impl Lexer {
@@ -82,19 +134,63 @@ impl Lexer {
             position: 0,
             line: 1,
             column: 1,
+            finished: false,
         }
     }
 
-    pub fn tokenize(&mut self) -> Result<Vec<Token>, String> {
+    /// Lexes the whole source at once. Iterate over the lexer instead to
+    /// produce tokens on demand, stopping at the first error.
+    ///
+    /// Lexing carries on past an error, so every error in the source is
+    /// reported at once. It resumes after the characters that caused the
+    /// error, which always include at least the first one.
+    pub fn tokenize(&mut self) -> Result<Vec<Token>, Vec<ExlineError>> {
         let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        loop {
+            match self.lex_token() {
+                Ok(token) if token.token_type == TokenType::Eof => {
+                    tokens.push(token);
+                    break;
+                }
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
+            }
+        }
+        self.finished = true;
 
-        while !self.is_at_end() {
-            let token = self.next_token()?;
-            tokens.push(token);
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
         }
+    }
+
+    /// Lexes the next token, recording where it ends, or the error at the
+    /// position it starts from.
+    fn lex_token(&mut self) -> Result<Token, ExlineError> {
+        self.skip_shebang();
+        // Errors point at the start of the token being lexed
+        self.skip_whitespace_except_newline();
+        let (line, column) = (self.line, self.column);
+
+        self.next_token()
+            .map(|mut token| {
+                token.end_line = self.line;
+                token.end_column = self.column;
+                token
+            })
+            .map_err(|message| ExlineError::new(message, line, column))
+    }
 
-        tokens.push(Token::new(TokenType::Eof, self.line, self.column));
-        Ok(tokens)
+    /// Skips a `#!` interpreter line at the very start of the source, so
+    /// scripts can be run directly as Unix executables.
+    fn skip_shebang(&mut self) {
+        if self.position == 0 && self.input.starts_with(&['#', '!']) {
+            while self.peek() != '\n' && !self.is_at_end() {
+                self.advance();
+            }
+        }
     }
 
     fn next_token(&mut self) -> Result<Token, String> {
@@ -124,8 +220,23 @@ impl Lexer {
                     Ok(Token::new(TokenType::Minus, line, column))
                 }
             }
-            '*' => Ok(Token::new(TokenType::Multiply, line, column)),
-            '/' => Ok(Token::new(TokenType::Divide, line, column)),
+            '*' => {
+                if self.peek() == '*' {
+                    self.advance();
+                    Ok(Token::new(TokenType::Power, line, column))
+                } else {
+                    Ok(Token::new(TokenType::Multiply, line, column))
+                }
+            }
+            '/' => {
+                if self.peek() == '/' {
+                    self.advance();
+                    Ok(Token::new(TokenType::IntDivide, line, column))
+                } else {
+                    Ok(Token::new(TokenType::Divide, line, column))
+                }
+            }
+            '%' => Ok(Token::new(TokenType::Modulo, line, column)),
             '=' => {
                 if self.peek() == '=' {
                     self.advance(); // consume the second '='
@@ -134,24 +245,66 @@ impl Lexer {
                     Ok(Token::new(TokenType::Assign, line, column))
                 }
             }
+            '\\' => {
+                // A line continuation: the statement carries on past the newline
+                self.skip_whitespace_except_newline();
+                if self.peek() != '\n' {
+                    return Err("Unexpected '\\': a line continuation must be the last thing on its line".to_string());
+                }
+                self.advance();
+                self.line += 1;
+                self.column = 1;
+                self.next_token()
+            }
+            '!' if self.peek() == '=' => {
+                self.advance();
+                Ok(Token::new(TokenType::NotEqual, line, column))
+            }
+            '&' => Ok(Token::new(TokenType::Ampersand, line, column)),
+            '|' => Ok(Token::new(TokenType::Pipe, line, column)),
+            '^' => Ok(Token::new(TokenType::Caret, line, column)),
+            '<' if self.peek() == '<' => {
+                self.advance();
+                Ok(Token::new(TokenType::ShiftLeft, line, column))
+            }
+            '>' if self.peek() == '>' => {
+                self.advance();
+                Ok(Token::new(TokenType::ShiftRight, line, column))
+            }
+            '<' if self.peek() == '=' => {
+                self.advance();
+                Ok(Token::new(TokenType::LessEqual, line, column))
+            }
+            '>' if self.peek() == '=' => {
+                self.advance();
+                Ok(Token::new(TokenType::GreaterEqual, line, column))
+            }
+            '<' => Ok(Token::new(TokenType::Less, line, column)),
+            '>' => Ok(Token::new(TokenType::Greater, line, column)),
             '(' => Ok(Token::new(TokenType::LeftParen, line, column)),
             ')' => Ok(Token::new(TokenType::RightParen, line, column)),
             ':' => Ok(Token::new(TokenType::Colon, line, column)),
-            '.' => Ok(Token::new(TokenType::Dot, line, column)),
-            '"' => self.string_literal(line, column),
-            '#' => {
-                if self.peek() == '{' {
+            '.' => {
+                if self.peek() == '.' && self.peek_next() == '.' {
                     self.advance();
-                    Ok(Token::new(TokenType::InterpolationStart, line, column))
+                    self.advance();
+                    Ok(Token::new(TokenType::Ellipsis, line, column))
                 } else {
-                    // Comment - skip to end of line
-                    while self.peek() != '\n' && !self.is_at_end() {
-                        self.advance();
-                    }
-                    self.next_token()
+                    Ok(Token::new(TokenType::Dot, line, column))
                 }
             }
-            '}' => Ok(Token::new(TokenType::InterpolationEnd, line, column)),
+            '?' if self.peek() == '.' => {
+                self.advance();
+                Ok(Token::new(TokenType::QuestionDot, line, column))
+            }
+            ',' => Ok(Token::new(TokenType::Comma, line, column)),
+            ';' => Ok(Token::new(TokenType::Semicolon, line, column)),
+            '{' => Ok(Token::new(TokenType::LeftBrace, line, column)),
+            '[' => Ok(Token::new(TokenType::LeftBracket, line, column)),
+            ']' => Ok(Token::new(TokenType::RightBracket, line, column)),
+            '"' => self.string_literal(line, column),
+            '\'' => self.char_literal(line, column),
+            '}' => Ok(Token::new(TokenType::RightBrace, line, column)),
             _ if ch.is_ascii_digit() => self.number(line, column),
             _ if ch.is_alphabetic() || ch == '_' => self.identifier(line, column),
             _ => Err(format!("Unexpected character: {}", ch)),
@@ -162,11 +315,12 @@ impl Lexer {
         let mut value = String::new();
 
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
+            let ch = self.advance();
+            if ch == '\n' {
                 self.line += 1;
                 self.column = 1;
             }
-            value.push(self.advance());
+            value.push(ch);
         }
 
         if self.is_at_end() {
@@ -177,6 +331,38 @@ impl Lexer {
         Ok(Token::new(TokenType::String(value), line, column))
     }
 
+    /// Lexes `'a'`. Supports the escapes `\n`, `\t`, `\r`, `\0`, `\\`, `\'`
+    /// and `\"`.
+    fn char_literal(&mut self, line: usize, column: usize) -> Result<Token, String> {
+        let value = match self.peek() {
+            '\'' => return Err("Empty character literal".to_string()),
+            '\n' | '\0' => return Err("Unterminated character literal".to_string()),
+            '\\' => {
+                self.advance();
+                if self.is_at_end() {
+                    return Err("Unterminated character literal".to_string());
+                }
+                match self.advance() {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '0' => '\0',
+                    '\\' => '\\',
+                    '\'' => '\'',
+                    '"' => '"',
+                    other => return Err(format!("Unknown escape sequence: \\{}", other)),
+                }
+            }
+            _ => self.advance(),
+        };
+
+        if self.peek() != '\'' {
+            return Err("Unterminated character literal".to_string());
+        }
+        self.advance(); // closing '
+        Ok(Token::new(TokenType::Char(value), line, column))
+    }
+
     fn number(&mut self, line: usize, column: usize) -> Result<Token, String> {
         let mut value = String::new();
         value.push(self.input[self.position - 1]); // Current character
@@ -185,9 +371,28 @@ impl Lexer {
             value.push(self.advance());
         }
 
+        // A '.' followed by a digit makes this a float literal
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
+            value.push(self.advance()); // consume '.'
+            while self.peek().is_ascii_digit() {
+                value.push(self.advance());
+            }
+
+            return value.parse::<f64>()
+                .map(|n| Token::new(TokenType::Float(n), line, column))
+                .map_err(|_| format!("Invalid float literal: {}", value));
+        }
+
         value.parse::<i64>()
             .map(|n| Token::new(TokenType::Integer(n), line, column))
-            .map_err(|_| "Invalid number".to_string())
+            .map_err(|e| match e.kind() {
+                IntErrorKind::PosOverflow => format!(
+                    "Integer literal too large: {} (the largest Int is {})",
+                    value,
+                    i64::MAX
+                ),
+                _ => format!("Invalid integer literal: {}", value),
+            })
     }
 
     fn identifier(&mut self, line: usize, column: usize) -> Result<Token, String> {
@@ -200,29 +405,63 @@ impl Lexer {
 
         let token_type = match value.as_str() {
             "Int" => TokenType::Int,
+            "Float" => TokenType::Float_,
+            "Char" => TokenType::Char_,
             "String" => TokenType::String_,
             "def" => TokenType::Def,
+            "const" => TokenType::Const,
             "end" => TokenType::End,
             "if" => TokenType::If,
             "else" => TokenType::Else,
+            "elif" => TokenType::Elif,
+            "while" => TokenType::While,
+            "do" => TokenType::Do,
+            "repeat" => TokenType::Repeat,
+            "until" => TokenType::Until,
+            "break" => TokenType::Break,
+            "continue" => TokenType::Continue,
+            "match" => TokenType::Match,
+            "case" => TokenType::Case,
+            "default" => TokenType::Default,
+            "try" => TokenType::Try,
+            "catch" => TokenType::Catch,
+            "raise" => TokenType::Raise,
+            "return" => TokenType::Return,
+            "and" => TokenType::And,
+            "or" => TokenType::Or,
+            "not" => TokenType::Not,
             "class" => TokenType::Class,
             "interface" => TokenType::Interface,
             "implements" => TokenType::Implements,
+            "static" => TokenType::Static,
+            "module" => TokenType::Module,
             "void" => TokenType::Void,
-            // Remove "print" from keywords - it should be treated as identifier
-            // Remove "new" from keywords - it should be treated as identifier (for Person.new() syntax)
+            "Map" => TokenType::Map,
+            "List" => TokenType::List,
+            "Function" => TokenType::Function,
+            // `print` is a built-in function and `new` is resolved by the parser
+            // in `Person.new()`, so neither is a keyword
             _ => TokenType::Identifier(value),
         };
 
         Ok(Token::new(token_type, line, column))
     }
 
+    /// Skips spaces and comments. A `#` comment runs up to but not including
+    /// the end of its line, so a statement followed by a comment still ends
+    /// with a `Newline`. Interpolation is lexed inside string literals, so
+    /// outside one even `#{` starts a comment.
     fn skip_whitespace_except_newline(&mut self) {
         while !self.is_at_end() {
             match self.peek() {
                 ' ' | '\t' | '\r' => {
                     self.advance();
                 }
+                '#' => {
+                    while self.peek() != '\n' && !self.is_at_end() {
+                        self.advance();
+                    }
+                }
                 _ => break,
             }
         }
@@ -243,11 +482,38 @@ impl Lexer {
         }
     }
 
+    fn peek_next(&self) -> char {
+        if self.position + 1 >= self.input.len() {
+            '\0'
+        } else {
+            self.input[self.position + 1]
+        }
+    }
+
     fn is_at_end(&self) -> bool {
         self.position >= self.input.len()
     }
 }
 
+/// Yields tokens one at a time, ending with `Eof`. Nothing follows `Eof`
+/// or an error.
+impl Iterator for Lexer {
+    type Item = Result<Token, ExlineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let result = self.lex_token();
+        self.finished = match &result {
+            Ok(token) => token.token_type == TokenType::Eof,
+            Err(_) => true,
+        };
+        Some(result)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -272,4 +538,259 @@ mod tests {
         assert_eq!(tokens[1].token_type, TokenType::Plus);
         assert_eq!(tokens[2].token_type, TokenType::Identifier("n2".to_string()));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_map_literal_tokens() {
+        let mut lexer = Lexer::new("Map m = { \"a\": 1, \"b\": 2 }");
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[0].token_type, TokenType::Map);
+        assert_eq!(tokens[3].token_type, TokenType::LeftBrace);
+        assert_eq!(tokens[5].token_type, TokenType::Colon);
+        assert_eq!(tokens[7].token_type, TokenType::Comma);
+        assert_eq!(tokens[11].token_type, TokenType::RightBrace);
+    }
+
+    #[test]
+    fn test_float_and_int_divide_tokens() {
+        let mut lexer = Lexer::new("3.5 / 2 // 1 % 2");
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[0].token_type, TokenType::Float(3.5));
+        assert_eq!(tokens[1].token_type, TokenType::Divide);
+        assert_eq!(tokens[2].token_type, TokenType::Integer(2));
+        assert_eq!(tokens[3].token_type, TokenType::IntDivide);
+        assert_eq!(tokens[5].token_type, TokenType::Modulo);
+    }
+
+    #[test]
+    fn test_comparison_tokens() {
+        let mut lexer = Lexer::new("< <= > >= << >>");
+        let types: Vec<TokenType> = lexer.tokenize().unwrap().into_iter().map(|t| t.token_type).collect();
+
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Less,
+                TokenType::LessEqual,
+                TokenType::Greater,
+                TokenType::GreaterEqual,
+                TokenType::ShiftLeft,
+                TokenType::ShiftRight,
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_end_positions() {
+        let tokens = Lexer::new("def f() -> String\n  \"a\nbc\" # done").tokenize().unwrap();
+        let spans: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.line, t.column, t.end_line, t.end_column))
+            .collect();
+
+        assert_eq!(
+            spans,
+            vec![
+                (1, 1, 1, 4),   // def
+                (1, 5, 1, 6),   // f
+                (1, 6, 1, 7),   // (
+                (1, 7, 1, 8),   // )
+                (1, 9, 1, 11),  // ->
+                (1, 12, 1, 18), // String
+                (1, 18, 2, 1),  // newline
+                (2, 3, 3, 4),   // "a\nbc"
+                (3, 11, 3, 11), // Eof after the comment
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokens_are_lexed_on_demand() {
+        // The error on line 2 isn't reached until the first line is consumed
+        let mut lexer = Lexer::new("Int x = 1\n@");
+        let first = lexer.next().unwrap().unwrap();
+        assert_eq!(first.token_type, TokenType::Int);
+
+        let rest: Vec<_> = lexer.collect();
+        assert_eq!(rest.len(), 5);
+        assert_eq!(rest[4].as_ref().unwrap_err(), &ExlineError::new("Unexpected character: @", 2, 1));
+
+        // Exactly one Eof, even after trailing whitespace or a comment
+        for source in ["x  ", "x # comment"] {
+            let tokens: Vec<_> = Lexer::new(source).map(|token| token.unwrap().token_type).collect();
+            assert_eq!(tokens, vec![TokenType::Identifier("x".to_string()), TokenType::Eof]);
+        }
+    }
+
+    #[test]
+    fn test_line_continuation() {
+        let types: Vec<TokenType> = Lexer::new("Int x = 1 + \\  \n  2\nx")
+            .map(|token| token.unwrap().token_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Int,
+                TokenType::Identifier("x".to_string()),
+                TokenType::Assign,
+                TokenType::Integer(1),
+                TokenType::Plus,
+                TokenType::Integer(2),
+                TokenType::Newline,
+                TokenType::Identifier("x".to_string()),
+                TokenType::Eof,
+            ]
+        );
+
+        let error = Lexer::new("Int x = 1 \\ 2").tokenize().unwrap_err().remove(0);
+        assert_eq!(error.message, "Unexpected '\\': a line continuation must be the last thing on its line");
+        assert_eq!(error.column, 11);
+        assert!(Lexer::new("x \\").tokenize().is_err());
+    }
+
+    #[test]
+    fn test_trailing_comment_keeps_newline() {
+        let types: Vec<TokenType> = Lexer::new("Int x = 1 # set x\nx #{ not interpolation }\n# whole line\n  # indented\nx # last")
+            .map(|token| token.unwrap().token_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Int,
+                TokenType::Identifier("x".to_string()),
+                TokenType::Assign,
+                TokenType::Integer(1),
+                TokenType::Newline,
+                TokenType::Identifier("x".to_string()),
+                TokenType::Newline,
+                TokenType::Newline,
+                TokenType::Newline,
+                TokenType::Identifier("x".to_string()),
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_print_and_new_are_identifiers() {
+        let types: Vec<TokenType> = Lexer::new("print(new)\nPerson.new()")
+            .map(|token| token.unwrap().token_type)
+            .collect();
+        assert_eq!(types[0], TokenType::Identifier("print".to_string()));
+        assert_eq!(types[2], TokenType::Identifier("new".to_string()));
+        assert_eq!(types[7], TokenType::Identifier("new".to_string()));
+    }
+
+    #[test]
+    fn test_tokenize_recovers_from_errors() {
+        let errors = Lexer::new("Int a = @\nInt b = 1 $ 2\nprint(a)").tokenize().unwrap_err();
+        let found: Vec<(String, usize, usize)> =
+            errors.into_iter().map(|e| (e.message, e.line, e.column)).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("Unexpected character: @".to_string(), 1, 9),
+                ("Unexpected character: $".to_string(), 2, 11),
+            ]
+        );
+
+        // Without errors it gives the same tokens as iterating
+        let source = "Int a = 1\nprint(\"#{a}\")";
+        let all: Vec<TokenType> = Lexer::new(source).tokenize().unwrap().into_iter().map(|t| t.token_type).collect();
+        let one: Vec<TokenType> = Lexer::new(source).map(|t| t.unwrap().token_type).collect();
+        assert_eq!(all, one);
+
+        // An error that runs to the end of the source is the last one
+        let errors = Lexer::new("` \"open").tokenize().unwrap_err();
+        let messages: Vec<String> = errors.into_iter().map(|e| e.message).collect();
+        assert_eq!(messages, vec!["Unexpected character: `", "Unterminated string"]);
+    }
+
+    #[test]
+    fn test_semicolon() {
+        let tokens = Lexer::new("a; b").tokenize().unwrap();
+        assert_eq!(tokens[1].token_type, TokenType::Semicolon);
+        assert_eq!(tokens[1].column, 2);
+    }
+
+    #[test]
+    fn test_question_dot() {
+        let types: Vec<TokenType> = Lexer::new("a?.b").tokenize().unwrap().into_iter().map(|t| t.token_type).collect();
+        assert_eq!(types[1], TokenType::QuestionDot);
+        assert_eq!(types[2], TokenType::Identifier("b".to_string()));
+        assert!(Lexer::new("a ? b").tokenize().is_err());
+    }
+
+    #[test]
+    fn test_shebang_line_is_skipped() {
+        let mut lexer = Lexer::new("#!/usr/bin/env exline\nInt x = 1");
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[0].token_type, TokenType::Newline);
+        assert_eq!(tokens[1].token_type, TokenType::Int);
+        assert_eq!(tokens[1].line, 2);
+    }
+
+    #[test]
+    fn test_char_literals() {
+        let mut lexer = Lexer::new(r"'a' '\n' '\''");
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[0].token_type, TokenType::Char('a'));
+        assert_eq!(tokens[1].token_type, TokenType::Char('\n'));
+        assert_eq!(tokens[2].token_type, TokenType::Char('\''));
+
+        assert_eq!(Lexer::new("''").tokenize().unwrap_err()[0].message, "Empty character literal");
+        assert_eq!(Lexer::new("'ab'").tokenize().unwrap_err()[0].message, "Unterminated character literal");
+    }
+
+    #[test]
+    fn test_unicode_identifiers_and_strings() {
+        let error = Lexer::new("String café = \"naïve 😀\"\nChar ñ = '🎉'; x😀").tokenize().unwrap_err();
+        assert_eq!(error, vec![ExlineError::new("Unexpected character: 😀", 2, 16)]);
+
+        let tokens = Lexer::new("String café = \"naïve 😀\"\nChar ñ = '🎉'").tokenize().unwrap();
+        let kinds: Vec<_> = tokens.iter().map(|t| (t.token_type.clone(), t.line, t.column)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (TokenType::String_, 1, 1),
+                (TokenType::Identifier("café".to_string()), 1, 8),
+                (TokenType::Assign, 1, 13),
+                (TokenType::String("naïve 😀".to_string()), 1, 15),
+                (TokenType::Newline, 1, 24),
+                (TokenType::Char_, 2, 1),
+                (TokenType::Identifier("ñ".to_string()), 2, 6),
+                (TokenType::Assign, 2, 8),
+                (TokenType::Char('🎉'), 2, 10),
+                (TokenType::Eof, 2, 13),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_integer_literal_too_large() {
+        let error = Lexer::new("Int big = 123456789012345678901234567890").tokenize().unwrap_err();
+        assert_eq!(
+            error,
+            vec![ExlineError::new(
+                "Integer literal too large: 123456789012345678901234567890 (the largest Int is 9223372036854775807)",
+                1,
+                11
+            )]
+        );
+        assert!(Lexer::new("9223372036854775807").tokenize().is_ok());
+    }
+
+    #[test]
+    fn test_error_position() {
+        let error = Lexer::new("Int a = 1\nInt b = @").tokenize().unwrap_err();
+        assert_eq!(error, vec![ExlineError::new("Unexpected character: @", 2, 9)]);
+
+        // An unterminated string is reported where it starts
+        let error = Lexer::new("print(\"abc\nd)").tokenize().unwrap_err();
+        assert_eq!(error, vec![ExlineError::new("Unterminated string", 1, 7)]);
+    }
+}
@@ -0,0 +1,11 @@
+//! The Exline language: lexing, parsing and running Exline source, and the
+//! tools built on them. The `exline` binary is the command-line front end;
+//! the library is what the benchmarks under `benches/` drive.
+
+pub mod error;
+pub mod lexer;
+pub mod parser;
+pub mod interpreter;
+pub mod formatter;
+pub mod optimizer;
+pub mod lint;
@@ -0,0 +1,170 @@
+use crate::error::ExlineError;
+use crate::lexer::{Lexer, TokenType};
+use std::collections::HashSet;
+use std::fmt;
+
+/// The longest line `--lint` accepts unless `--max-width` says otherwise.
+pub const DEFAULT_MAX_WIDTH: usize = 100;
+
+/// A style problem found by `--lint`. Unlike an `ExlineError`, it doesn't
+/// stop the program from running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintWarning {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl LintWarning {
+    fn new(message: impl Into<String>, line: usize, column: usize) -> Self {
+        Self {
+            message: message.into(),
+            line,
+            column,
+        }
+    }
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Indent {
+    Tabs,
+    Spaces,
+}
+
+impl Indent {
+    fn describe(self) -> &'static str {
+        match self {
+            Indent::Tabs => "tabs",
+            Indent::Spaces => "spaces",
+        }
+    }
+}
+
+/// Checks source for mixed indentation, trailing whitespace and lines longer
+/// than `max_width` characters, returning the warnings in source order.
+/// Whitespace inside a multi-line string is part of the string, so the
+/// lines it covers are only checked for width. Source that doesn't lex is
+/// an error, since its strings can't be told apart from code; every lexer
+/// error is returned.
+pub fn lint(source: &str, max_width: usize) -> Result<Vec<LintWarning>, Vec<ExlineError>> {
+    let (continued, inside) = string_lines(source)?;
+    let mut warnings = Vec::new();
+    let mut file_indent = None;
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let chars: Vec<char> = text.chars().collect();
+
+        let width = chars.len();
+        if width > max_width {
+            warnings.push(LintWarning::new(
+                format!("Line is {} characters long, more than {}", width, max_width),
+                line,
+                max_width + 1,
+            ));
+        }
+
+        let content_end = chars.iter().rposition(|c| !c.is_whitespace()).map_or(0, |i| i + 1);
+        if content_end < width && !continued.contains(&line) {
+            warnings.push(LintWarning::new("Trailing whitespace", line, content_end + 1));
+        }
+
+        // Blank lines have no indentation to speak of
+        if content_end == 0 || inside.contains(&line) {
+            continue;
+        }
+        let indentation = &chars[..chars.iter().position(|c| !c.is_whitespace()).unwrap_or(0)];
+        let indent = match (indentation.contains(&'\t'), indentation.contains(&' ')) {
+            (true, true) => {
+                warnings.push(LintWarning::new("Indentation mixes tabs and spaces", line, 1));
+                continue;
+            }
+            (true, false) => Indent::Tabs,
+            (false, true) => Indent::Spaces,
+            (false, false) => continue,
+        };
+        match file_indent {
+            None => file_indent = Some((indent, line)),
+            Some((first, first_line)) if first != indent => warnings.push(LintWarning::new(
+                format!(
+                    "Indented with {}, but line {} is indented with {}",
+                    indent.describe(),
+                    first_line,
+                    first.describe()
+                ),
+                line,
+                1,
+            )),
+            Some(_) => {}
+        }
+    }
+
+    warnings.sort_by_key(|warning| (warning.line, warning.column));
+    Ok(warnings)
+}
+
+/// Finds the lines a multi-line string literal runs on from: those where
+/// the string continues past the end of the line, and those it continues
+/// onto.
+fn string_lines(source: &str) -> Result<(HashSet<usize>, HashSet<usize>), Vec<ExlineError>> {
+    let mut continued = HashSet::new();
+    let mut inside = HashSet::new();
+    for token in Lexer::new(source).tokenize()? {
+        if matches!(token.token_type, TokenType::String(_)) {
+            continued.extend(token.line..token.end_line);
+            inside.extend(token.line + 1..=token.end_line);
+        }
+    }
+    Ok((continued, inside))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(source: &str, max_width: usize) -> Vec<(usize, usize, String)> {
+        lint(source, max_width)
+            .unwrap()
+            .into_iter()
+            .map(|warning| (warning.line, warning.column, warning.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_source_has_no_warnings() {
+        let source = "def f() -> Int\n  if 1\n    return 1\n  end\n\n  return 0\nend\r\n";
+        assert!(messages(source, DEFAULT_MAX_WIDTH).is_empty());
+    }
+
+    #[test]
+    fn test_whitespace_and_width_warnings() {
+        let source = "Int a = 1  \nwhile a\n\ta = 0\n \tprint(a)\n  print(\"long line\")\n  \nend";
+        assert_eq!(
+            messages(source, 16),
+            vec![
+                (1, 10, "Trailing whitespace".to_string()),
+                (4, 1, "Indentation mixes tabs and spaces".to_string()),
+                (5, 1, "Indented with spaces, but line 3 is indented with tabs".to_string()),
+                (5, 17, "Line is 20 characters long, more than 16".to_string()),
+                (6, 1, "Trailing whitespace".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_whitespace_inside_strings_is_ignored() {
+        // The string's own lines keep their spaces and tabs, but code after it is still checked
+        let source = "String s = \"a  \n\t b\n\" \n  print(s)";
+        assert_eq!(messages(source, DEFAULT_MAX_WIDTH), vec![(3, 2, "Trailing whitespace".to_string())]);
+
+        let errors = lint("Int a = @\nString s = \"open", DEFAULT_MAX_WIDTH).unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["Unexpected character: @", "Unterminated string"]);
+    }
+}
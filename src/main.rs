@@ -1,48 +1,389 @@
-mod lexer;
-mod parser;
-mod interpreter;
+use exline::{error, formatter, interpreter, lexer, lint, optimizer, parser};
 
 #[cfg(test)]
 mod debug;
 
+use error::ExlineError;
 use lexer::Lexer;
-use parser::Parser;
-use interpreter::Interpreter;
+use parser::{Parser, Program};
+use interpreter::{Interpreter, Value, DEFAULT_MAX_CALL_DEPTH};
 use std::env;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What the CLI should do with the given file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Run,
+    Format,
+    AstJson,
+    Check,
+    Lint,
+    Help,
+    Version,
+}
+
+#[derive(Debug)]
+struct Options {
+    mode: Mode,
+    show_tokens: bool,
+    show_ast: bool,
+    time: bool,
+    /// Fold constant expressions before running.
+    optimize: bool,
+    /// Print each statement and call as it runs.
+    trace: bool,
+    /// How many calls may be nested before the program fails.
+    max_depth: usize,
+    /// With `--lint-strict`, warnings make the exit status non-zero.
+    lint_strict: bool,
+    /// Longest line `--lint` accepts.
+    max_width: usize,
+    /// Never color error messages, even on a terminal.
+    no_color: bool,
+    path: Option<String>,
+    /// Source given with `--eval`, run instead of a file.
+    eval: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Run,
+            show_tokens: false,
+            show_ast: false,
+            time: false,
+            optimize: false,
+            trace: false,
+            max_depth: DEFAULT_MAX_CALL_DEPTH,
+            lint_strict: false,
+            max_width: lint::DEFAULT_MAX_WIDTH,
+            no_color: false,
+            path: None,
+            eval: None,
+        }
+    }
+}
+
+const VERSION: &str = "Exline v0.1.0";
+
+const OPTIONS_HELP: &str = "\
+With no file, starts the REPL.
+
+Options:
+  --fmt         Print the file re-formatted
+  --ast-json    Print the parsed file as JSON
+  --check       Parse the file without running it
+  --lint        Warn about mixed indentation, trailing whitespace and long lines
+  --lint-strict Like --lint, but exit with an error if there are warnings
+  --max-width n Longest line --lint accepts (default 100)
+  --eval code   Run the given code instead of a file
+  --tokens      Print the tokens before running
+  --ast         Print the syntax tree before running
+  --time        Report how long lexing, parsing and running took
+  --opt         Fold constant expressions before running
+  --trace       Print each statement and call to stderr as it runs
+  --max-depth n Fail once more than n calls are nested (default 1000, at most 10000)
+  --no-color    Don't color error messages (also set by NO_COLOR)
+  --help        Show this help
+  --version     Show the version";
+
+fn usage(program: &str) -> String {
+    format!(
+        "Usage: {} [--tokens] [--ast] [--time] [--opt] [--trace] [--max-depth n] [--no-color] [--fmt | --ast-json | --check | --lint | --lint-strict] [--max-width n] [file.exl | --eval code]",
+        program
+    )
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fmt" => options.mode = Mode::Format,
+            "--ast-json" => options.mode = Mode::AstJson,
+            "--check" => options.mode = Mode::Check,
+            "--lint" => options.mode = Mode::Lint,
+            "--lint-strict" => {
+                options.mode = Mode::Lint;
+                options.lint_strict = true;
+            }
+            "--max-width" => match args.next().map(|width| width.parse()) {
+                Some(Ok(width)) if width > 0 => options.max_width = width,
+                _ => return Err("--max-width requires a positive number of characters".to_string()),
+            },
+            "--tokens" => options.show_tokens = true,
+            "--ast" => options.show_ast = true,
+            "--time" => options.time = true,
+            "--opt" => options.optimize = true,
+            "--trace" => options.trace = true,
+            "--no-color" => options.no_color = true,
+            "--max-depth" => match args.next().map(|depth| depth.parse()) {
+                Some(Ok(depth)) if depth > 0 && depth <= MAX_CALL_DEPTH_LIMIT => options.max_depth = depth,
+                _ => {
+                    return Err(format!("--max-depth requires a number of calls from 1 to {}", MAX_CALL_DEPTH_LIMIT));
+                }
+            },
+            "--eval" => match args.next() {
+                Some(source) => options.eval = Some(source.clone()),
+                None => return Err("--eval requires code to run".to_string()),
+            },
+            "--help" => options.mode = Mode::Help,
+            "--version" => options.mode = Mode::Version,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ if options.path.is_some() => return Err("Only one file can be given".to_string()),
+            _ => options.path = Some(arg.clone()),
+        }
+    }
+
+    let needs_file = matches!(options.mode, Mode::Format | Mode::AstJson | Mode::Check | Mode::Lint);
+    if needs_file && options.path.is_none() {
+        return Err("A file is required for --fmt, --ast-json, --check and --lint".to_string());
+    }
+    if options.eval.is_some() && (options.path.is_some() || options.mode != Mode::Run) {
+        return Err("--eval can't be combined with a file or with --fmt, --ast-json, --check and --lint".to_string());
+    }
+
+    Ok(options)
+}
+
+/// Stack for the thread that runs programs, per call the depth limit allows.
+/// Each Exline call nests several Rust calls, and unoptimized builds use up to
+/// about 150KB of stack for a call made from inside loops and `try` blocks, so
+/// the default 8MB would overflow well before the call depth limit.
+const STACK_PER_CALL: usize = 256 * 1024;
+
+/// The most `--max-depth` accepts, which keeps the interpreter thread's stack
+/// at a size the system can reserve.
+const MAX_CALL_DEPTH_LIMIT: usize = 10_000;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() > 2 {
-        eprintln!("Usage: {} [file.exl]", args[0]);
-        std::process::exit(64);
-    } else if args.len() == 2 {
-        run_file(&args[1]);
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(err) => {
+            let no_color = args.iter().any(|arg| arg == "--no-color");
+            print_error(&err, color_enabled(no_color));
+            eprintln!("{}", usage(&args[0]));
+            std::process::exit(64);
+        }
+    };
+
+    let runner = thread::Builder::new()
+        .stack_size(options.max_depth.max(DEFAULT_MAX_CALL_DEPTH) * STACK_PER_CALL)
+        .spawn(move || run_main(&args[0], &options))
+        .expect("failed to start the interpreter thread");
+    if runner.join().is_err() {
+        std::process::exit(70);
+    }
+}
+
+fn run_main(program: &str, options: &Options) {
+    if let Some(source) = &options.eval {
+        run_eval(source, options);
+        return;
+    }
+
+    match (&options.path, options.mode) {
+        (_, Mode::Help) => println!("{}\n\n{}", usage(program), OPTIONS_HELP),
+        (_, Mode::Version) => println!("{}", VERSION),
+        (Some(path), Mode::Format) => format_file(path, options),
+        (Some(path), Mode::AstJson) => dump_ast_json(path, options),
+        (Some(path), Mode::Check) => check_file(path, options),
+        (Some(path), Mode::Lint) => lint_file(path, options),
+        (Some(path), Mode::Run) => run_file(path, options),
+        (None, _) => run_repl(options),
+    }
+}
+
+/// Whether error messages are colored: only when stderr is a terminal, and
+/// not with `--no-color` or a non-empty `NO_COLOR` environment variable.
+fn color_enabled(no_color: bool) -> bool {
+    let no_color_env = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    !no_color && !no_color_env && io::stderr().is_terminal()
+}
+
+/// Prints an error report to stderr after an `Error:` label.
+fn print_error(report: &str, color: bool) {
+    if color {
+        eprintln!("{} {}", error::red("Error:"), error::colorize(report));
+    } else {
+        eprintln!("Error: {}", report);
+    }
+}
+
+/// Prints an error report to stderr after the name of the file it is in.
+fn print_file_error(path: &str, report: &str, color: bool) {
+    if color {
+        eprintln!("{}: {}", path, error::colorize(report));
     } else {
-        run_repl();
+        eprintln!("{}: {}", path, report);
     }
 }
 
-fn run_file(path: &str) {
-    let source = match fs::read_to_string(path) {
+fn read_source(path: &str) -> String {
+    match fs::read_to_string(path) {
         Ok(content) => content,
         Err(err) => {
             eprintln!("Error reading file '{}': {}", path, err);
             std::process::exit(74);
         }
-    };
+    }
+}
+
+fn run_file(path: &str, options: &Options) {
+    let source = read_source(path);
+
+    if let Err(err) = run(&source, options) {
+        print_error(&err, color_enabled(options.no_color));
+        std::process::exit(70);
+    }
+}
 
-    if let Err(err) = run(&source) {
-        eprintln!("Error: {}", err);
+/// Runs code given on the command line, exiting like `run_file` does.
+fn run_eval(source: &str, options: &Options) {
+    if let Err(err) = run(source, options) {
+        print_error(&err, color_enabled(options.no_color));
         std::process::exit(70);
     }
 }
 
-fn run_repl() {
-    println!("Exline v0.1.0 REPL");
-    println!("Type 'exit' to quit");
+fn format_file(path: &str, options: &Options) {
+    let source = read_source(path);
+
+    match format_source(&source) {
+        Ok(formatted) => print!("{}", formatted),
+        Err(err) => {
+            print_error(&err, color_enabled(options.no_color));
+            std::process::exit(65);
+        }
+    }
+}
+
+fn dump_ast_json(path: &str, options: &Options) {
+    let source = read_source(path);
+
+    let json = parse_source(&source).and_then(|program| {
+        serde_json::to_string_pretty(&program).map_err(|e| format!("Serialization error: {}", e))
+    });
+
+    match json {
+        Ok(json) => println!("{}", json),
+        Err(err) => {
+            print_error(&err, color_enabled(options.no_color));
+            std::process::exit(65);
+        }
+    }
+}
+
+/// Lexes and parses a file without running it, for editors and CI.
+fn check_file(path: &str, options: &Options) {
+    let source = read_source(path);
+
+    match parse_source(&source) {
+        Ok(_) => println!("{}: OK", path),
+        Err(err) => {
+            print_file_error(path, &err, color_enabled(options.no_color));
+            std::process::exit(65);
+        }
+    }
+}
+
+/// Prints each lint warning on its own line. Only `--lint-strict` treats
+/// warnings as a failure.
+fn lint_file(path: &str, options: &Options) {
+    let source = read_source(path);
+
+    match lint::lint(&source, options.max_width) {
+        Ok(warnings) if warnings.is_empty() => println!("{}: OK", path),
+        Ok(warnings) => {
+            for warning in &warnings {
+                println!("{}: Warning: {}", path, warning);
+            }
+            if options.lint_strict {
+                std::process::exit(65);
+            }
+        }
+        Err(errors) => {
+            let report = lexer_error_report(errors, &source);
+            print_file_error(path, &report, color_enabled(options.no_color));
+            std::process::exit(65);
+        }
+    }
+}
+
+/// Renders every lexer error, each followed by the line it is on and a
+/// caret under its column.
+fn lexer_error_report(errors: Vec<ExlineError>, source: &str) -> String {
+    errors
+        .iter()
+        .map(|e| format!("Lexer error: {}", e.report(source)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Renders every collected parser error, each followed by the line it is
+/// on and a caret under its column.
+fn parser_error_report(errors: Vec<ExlineError>, source: &str) -> String {
+    errors
+        .iter()
+        .map(|e| format!("Parser error: {}", e.report(source)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_source(source: &str) -> Result<String, String> {
+    let program = parse_source(source)?;
+    Ok(formatter::format(&program))
+}
+
+fn parse_source(source: &str) -> Result<Program, String> {
+    let mut lexer = Lexer::new(source);
+    let tokens = lexer.tokenize().map_err(|errors| lexer_error_report(errors, source))?;
+
+    let mut parser = Parser::new(tokens);
+    parser.parse().map_err(|errors| parser_error_report(errors, source))
+}
+
+const REPL_HELP: &str = "\
+Commands:
+  :env         List defined variables, functions, classes and interfaces
+  :load path   Run a file in this session, keeping what it defines
+  :help        Show this help
+  exit         Quit the REPL";
+
+/// Handles a REPL line starting with `:`, returning the text to show.
+fn repl_command(input: &str, options: &Options, interpreter: &mut Interpreter) -> Result<String, String> {
+    let (command, argument) = match input.split_once(char::is_whitespace) {
+        Some((command, argument)) => (command, argument.trim()),
+        None => (input, ""),
+    };
+
+    match command {
+        ":env" => Ok(interpreter.environment().describe()),
+        ":help" => Ok(REPL_HELP.to_string()),
+        ":load" if argument.is_empty() => Err(":load requires a file path".to_string()),
+        ":load" => {
+            let source = fs::read_to_string(argument)
+                .map_err(|err| format!("Error reading file '{}': {}", argument, err))?;
+            run_in(&source, options, interpreter)?;
+            Ok(format!("Loaded {}", argument))
+        }
+        _ => Err(format!("Unknown command: {} (try :help)", command)),
+    }
+}
+
+fn run_repl(options: &Options) {
+    println!("{} REPL", VERSION);
+    println!("Type 'exit' to quit, or ':help' for commands");
+
+    // One interpreter for the whole session, so definitions carry over between lines
+    let mut interpreter = new_interpreter(options);
+    let color = color_enabled(options.no_color);
 
     loop {
         print!("> ");
@@ -60,8 +401,19 @@ fn run_repl() {
                     continue;
                 }
 
-                if let Err(err) = run(input) {
-                    eprintln!("Error: {}", err);
+                if input.starts_with(':') {
+                    match repl_command(input, options, &mut interpreter) {
+                        Ok(text) => println!("{}", text),
+                        Err(err) => print_error(&err, color),
+                    }
+                    continue;
+                }
+
+                // Echo the value of a bare expression, like a calculator
+                match run_in(input, options, &mut interpreter) {
+                    Ok(Some(value)) => println!("{}", value),
+                    Ok(None) => {}
+                    Err(err) => print_error(&err, color),
                 }
             }
             Err(err) => {
@@ -72,13 +424,87 @@ fn run_repl() {
     }
 }
 
-fn run(source: &str) -> Result<(), String> {
+/// How long each phase took, reported with `--time`.
+#[derive(Debug, Default)]
+struct Timings {
+    lex: Duration,
+    parse: Duration,
+    run: Duration,
+}
+
+impl std::fmt::Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "lex: {:.1?}, parse: {:.1?}, run: {:.1?}", self.lex, self.parse, self.run)
+    }
+}
+
+/// An interpreter set up with the debugging options from the command line.
+fn new_interpreter(options: &Options) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_max_call_depth(options.max_depth);
+    if options.trace {
+        interpreter.trace_to(Box::new(io::stderr()));
+    }
+    interpreter
+}
+
+/// Runs a whole program in a fresh interpreter.
+fn run(source: &str, options: &Options) -> Result<(), String> {
+    let mut timings = Timings::default();
+    let program = lex_and_parse(source, options, &mut timings)?;
+
+    let mut interpreter = new_interpreter(options);
+    let started = Instant::now();
+    let result = interpreter.interpret(program).map_err(|e| format!("Runtime error: {}", e));
+    timings.run = started.elapsed();
+
+    report_timings(&timings, options);
+    exit_if_requested(&interpreter);
+    result
+}
+
+/// Runs source in an existing interpreter, keeping anything it defines.
+/// Returns the value of the last statement if it is a non-Void expression.
+fn run_in(source: &str, options: &Options, interpreter: &mut Interpreter) -> Result<Option<Value>, String> {
+    let mut timings = Timings::default();
+    let program = lex_and_parse(source, options, &mut timings)?;
+
+    let started = Instant::now();
+    let result = interpreter
+        .interpret_with_result(program)
+        .map_err(|e| format!("Runtime error: {}", e));
+    timings.run = started.elapsed();
+
+    report_timings(&timings, options);
+    exit_if_requested(interpreter);
+    result
+}
+
+/// Ends the process with the code the program passed to `exit`, if any. The
+/// program's output was flushed when it called `exit`.
+fn exit_if_requested(interpreter: &Interpreter) {
+    if let Some(code) = interpreter.exit_code() {
+        std::process::exit(code);
+    }
+}
+
+/// Timings go to stderr so they don't mix with the program's output.
+fn report_timings(timings: &Timings, options: &Options) {
+    if options.time {
+        eprintln!("{}", timings);
+    }
+}
+
+/// Front end shared by file and REPL execution, honouring `--tokens` and `--ast`.
+fn lex_and_parse(source: &str, options: &Options, timings: &mut Timings) -> Result<Program, String> {
     // Tokenize
+    let started = Instant::now();
     let mut lexer = Lexer::new(source);
-    let tokens = lexer.tokenize().map_err(|e| format!("Lexer error: {}", e))?;
+    let tokens = lexer.tokenize().map_err(|errors| lexer_error_report(errors, source))?;
+    timings.lex = started.elapsed();
 
     // Debug: print tokens
-    if std::env::var("DEBUG_TOKENS").is_ok() {
+    if options.show_tokens {
         println!("Tokens:");
         for (i, token) in tokens.iter().enumerate() {
             println!("  {}: {:?}", i, token.token_type);
@@ -86,19 +512,20 @@ fn run(source: &str) -> Result<(), String> {
     }
 
     // Parse
+    let started = Instant::now();
     let mut parser = Parser::new(tokens);
-    let program = parser.parse().map_err(|e| format!("Parser error: {}", e))?;
+    let mut program = parser.parse().map_err(|errors| parser_error_report(errors, source))?;
+    if options.optimize {
+        program = optimizer::optimize(program);
+    }
+    timings.parse = started.elapsed();
 
     // Debug: print AST
-    if std::env::var("DEBUG_AST").is_ok() {
+    if options.show_ast {
         println!("AST: {:#?}", program);
     }
 
-    // Interpret
-    let mut interpreter = Interpreter::new();
-    interpreter.interpret(program).map_err(|e| format!("Runtime error: {}", e))?;
-
-    Ok(())
+    Ok(program)
 }
 
 #[cfg(test)]
@@ -113,7 +540,7 @@ Int n2 = 1
 print(n1 + n2)
 "#;
 
-        let result = run(source);
+        let result = run(source, &Options::default());
         assert!(result.is_ok());
     }
 
@@ -124,7 +551,225 @@ String name = "Exline"
 print(name)
 "#;
 
-        let result = run(source);
+        let result = run(source, &Options::default());
         assert!(result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_ast_json_serialization() {
+        let program = parse_source("Int n1 = 1").unwrap();
+        let json = serde_json::to_value(&program).unwrap();
+
+        let declaration = &json["statements"][0]["VariableDeclaration"];
+        assert_eq!(declaration["name"], "n1");
+        assert_eq!(declaration["var_type"], "Int");
+        assert_eq!(declaration["value"]["Integer"], 1);
+    }
+
+    #[test]
+    fn test_parse_args_debug_flags() {
+        let args: Vec<String> = ["--tokens", "--ast", "main.exl"].iter().map(|s| s.to_string()).collect();
+        let options = parse_args(&args).unwrap();
+
+        assert!(options.show_tokens);
+        assert!(options.show_ast);
+        assert_eq!(options.mode, Mode::Run);
+        assert_eq!(options.path.as_deref(), Some("main.exl"));
+    }
+
+    #[test]
+    fn test_opt_flag() {
+        let args: Vec<String> = ["--opt", "main.exl"].iter().map(|s| s.to_string()).collect();
+        let options = parse_args(&args).unwrap();
+        assert!(options.optimize);
+
+        let program = lex_and_parse("Int a = 2 * 3", &options, &mut Timings::default()).unwrap();
+        assert!(matches!(&program.statements[0], parser::Statement::VariableDeclaration { value: parser::Expression::Integer(6), .. }));
+        assert!(run("print(1 // 0)", &options).is_err());
+    }
+
+    #[test]
+    fn test_timings_display() {
+        let timings = Timings {
+            lex: Duration::from_micros(1200),
+            parse: Duration::from_micros(800),
+            run: Duration::from_millis(14),
+        };
+        assert_eq!(timings.to_string(), "lex: 1.2ms, parse: 800.0µs, run: 14.0ms");
+
+        let args = vec!["--time".to_string(), "main.exl".to_string()];
+        assert!(parse_args(&args).unwrap().time);
+    }
+
+    #[test]
+    fn test_trace_flag() {
+        let args = vec!["--trace".to_string(), "main.exl".to_string()];
+        assert!(parse_args(&args).unwrap().trace);
+        assert!(!parse_args(&args[1..]).unwrap().trace);
+    }
+
+    #[test]
+    fn test_no_color_flag() {
+        assert!(!Options::default().no_color);
+        let args: Vec<String> = ["--no-color", "main.exl"].iter().map(|s| s.to_string()).collect();
+        assert!(parse_args(&args).unwrap().no_color);
+        assert!(!color_enabled(true));
+    }
+
+    #[test]
+    fn test_lint_flags() {
+        let args: Vec<String> = ["--lint", "main.exl"].iter().map(|s| s.to_string()).collect();
+        let options = parse_args(&args).unwrap();
+        assert_eq!(options.mode, Mode::Lint);
+        assert!(!options.lint_strict);
+        assert_eq!(options.max_width, lint::DEFAULT_MAX_WIDTH);
+
+        let args: Vec<String> = ["--lint-strict", "--max-width", "80", "main.exl"].iter().map(|s| s.to_string()).collect();
+        let options = parse_args(&args).unwrap();
+        assert_eq!((options.mode, options.lint_strict, options.max_width), (Mode::Lint, true, 80));
+
+        let args = vec!["--lint".to_string()];
+        assert_eq!(parse_args(&args).unwrap_err(), "A file is required for --fmt, --ast-json, --check and --lint");
+        let args = vec!["--max-width".to_string(), "0".to_string()];
+        assert_eq!(parse_args(&args).unwrap_err(), "--max-width requires a positive number of characters");
+    }
+
+    #[test]
+    fn test_max_depth_flag() {
+        let args: Vec<String> = ["--max-depth", "50", "main.exl"].iter().map(|s| s.to_string()).collect();
+        let options = parse_args(&args).unwrap();
+        assert_eq!(options.max_depth, 50);
+        assert_eq!(parse_args(&args[2..]).unwrap().max_depth, DEFAULT_MAX_CALL_DEPTH);
+
+        let err = run("def f(n: Int) -> Int\n  return f(n)\nend\nf(1)", &options).unwrap_err();
+        assert!(err.starts_with("Runtime error: Maximum recursion depth exceeded (50 calls) in f"), "{}", err);
+
+        let args = vec!["--max-depth".to_string(), MAX_CALL_DEPTH_LIMIT.to_string()];
+        assert_eq!(parse_args(&args).unwrap().max_depth, MAX_CALL_DEPTH_LIMIT);
+        for bad in ["0", "many", "10001"] {
+            let args = vec!["--max-depth".to_string(), bad.to_string()];
+            assert_eq!(parse_args(&args).unwrap_err(), "--max-depth requires a number of calls from 1 to 10000");
+        }
+    }
+
+    #[test]
+    fn test_parse_args_eval() {
+        let args: Vec<String> = ["--time", "--eval", "print(1 + 2)"].iter().map(|s| s.to_string()).collect();
+        let options = parse_args(&args).unwrap();
+        assert_eq!(options.eval.as_deref(), Some("print(1 + 2)"));
+        assert!(options.time);
+        assert!(options.path.is_none());
+
+        assert!(parse_args(&["--eval".to_string()]).is_err());
+        let with_file: Vec<String> = ["--eval", "print(1)", "main.exl"].iter().map(|s| s.to_string()).collect();
+        assert!(parse_args(&with_file).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_rejects_unknown_flag() {
+        let args = vec!["--bogus".to_string()];
+        assert!(parse_args(&args).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_help_and_version() {
+        let options = parse_args(&["--version".to_string()]).unwrap();
+        assert_eq!(options.mode, Mode::Version);
+
+        // --help wins over a file argument and doesn't require one
+        let options = parse_args(&["--help".to_string(), "main.exl".to_string()]).unwrap();
+        assert_eq!(options.mode, Mode::Help);
+    }
+
+    #[test]
+    fn test_check_does_not_execute() {
+        // An undefined variable is a runtime error, so checking still succeeds
+        assert!(parse_source("print(undefined_variable)").is_ok());
+        assert!(parse_source("def broken(").is_err());
+    }
+
+    #[test]
+    fn test_reports_every_parser_error() {
+        let source = "Int a = )\nprint(a)\nInt b = )\n";
+        let report = parse_source(source).unwrap_err();
+
+        assert_eq!(
+            report,
+            "Parser error: Expected expression at line 1, column 9\n  Int a = )\n          ^\n\
+             Parser error: Expected expression at line 3, column 9\n  Int b = )\n          ^"
+        );
+    }
+
+    #[test]
+    fn test_reports_every_lexer_error() {
+        let report = parse_source("Int a = @\nInt b = $").unwrap_err();
+        assert_eq!(
+            report,
+            "Lexer error: Unexpected character: @ at line 1, column 9\n  Int a = @\n          ^\n\
+             Lexer error: Unexpected character: $ at line 2, column 9\n  Int b = $\n          ^"
+        );
+    }
+
+    #[test]
+    fn test_repl_commands() {
+        let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+        let program = parse_source("Int x = 5\nclass Person\n  String name\nend").unwrap();
+        interpreter.interpret(program).unwrap();
+
+        let options = Options::default();
+
+        let env = repl_command(":env", &options, &mut interpreter).unwrap();
+        assert!(env.contains("Variables:\n  x: Int = 5\n"));
+        assert!(env.contains("  len(value: Map) -> Int\n"));
+        assert!(env.ends_with("Classes:\n  Person\nInterfaces:"));
+
+        assert!(repl_command(":help", &options, &mut interpreter).unwrap().contains(":load"));
+        assert!(repl_command(":bogus", &options, &mut interpreter).is_err());
+    }
+
+    #[test]
+    fn test_repl_load() {
+        let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+        let options = Options::default();
+        run_in("Int base = 10", &options, &mut interpreter).unwrap();
+
+        let path = env::temp_dir().join(format!("exline_load_{}.exl", std::process::id()));
+        fs::write(&path, "def addBase(n: Int) -> Int\n  n + base\nend\nInt loaded = addBase(5)").unwrap();
+        let command = format!(":load {}", path.display());
+        let result = repl_command(&command, &options, &mut interpreter);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(result, Ok(format!("Loaded {}", path.display())));
+        run_in("Int again = addBase(loaded)", &options, &mut interpreter).unwrap();
+        assert!(interpreter.environment().describe().contains("  again: Int = 25\n"));
+
+        // Errors are reported to the session rather than ending it
+        assert!(repl_command(":load /no/such/file.exl", &options, &mut interpreter)
+            .unwrap_err()
+            .starts_with("Error reading file '/no/such/file.exl'"));
+        assert_eq!(repl_command(":load", &options, &mut interpreter), Err(":load requires a file path".to_string()));
+    }
+
+    #[test]
+    fn test_repl_lines_share_state() {
+        let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+        let options = Options::default();
+
+        run_in("Int x = 5", &options, &mut interpreter).unwrap();
+        run_in("def double(n: Int) -> Int\n  n * 2\nend", &options, &mut interpreter).unwrap();
+        run_in("Int y = double(x)", &options, &mut interpreter).unwrap();
+
+        assert!(interpreter.environment().describe().contains("  y: Int = 10\n"));
+        assert!(run("print(x)", &options).is_err());
+    }
+
+    #[test]
+    fn test_repl_result_value() {
+        let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+        let options = Options::default();
+
+        assert_eq!(run_in("1 + 2", &options, &mut interpreter), Ok(Some(Value::Integer(3))));
+        assert_eq!(run_in("Int x = 1", &options, &mut interpreter), Ok(None));
+        assert_eq!(run_in("print(x)", &options, &mut interpreter), Ok(None));
+    }
+}
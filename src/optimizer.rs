@@ -0,0 +1,225 @@
+use crate::parser::{BinaryOperator, Expression, KeywordArgument, Parameter, Program, Statement, UnaryOperator};
+
+/// Folds constant Int and String subexpressions, so `2 + 3` becomes `5` and
+/// `"a" + "b"` becomes `"ab"`. An operation is only folded when the
+/// interpreter would produce exactly the same value; anything that would be
+/// a runtime error, such as `1 // 0` or an overflowing `+`, is left in place
+/// so it still fails when (and if) it runs.
+pub fn optimize(mut program: Program) -> Program {
+    for statement in &mut program.statements {
+        fold_statement(statement);
+    }
+    program
+}
+
+fn fold_statements(statements: &mut [Statement]) {
+    for statement in statements {
+        fold_statement(statement);
+    }
+}
+
+fn fold_parameters(parameters: &mut [Parameter]) {
+    for default in parameters.iter_mut().filter_map(|param| param.default.as_mut()) {
+        fold_expression(default);
+    }
+}
+
+fn fold_statement(statement: &mut Statement) {
+    match statement {
+        Statement::VariableDeclaration { value, .. }
+        | Statement::ConstDeclaration { value, .. }
+        | Statement::Raise { message: value, .. }
+        | Statement::Expression(value, _) => fold_expression(value),
+        Statement::Return { value, .. } => {
+            if let Some(value) = value {
+                fold_expression(value);
+            }
+        }
+        Statement::MultipleDeclaration { declarations, .. } => fold_statements(declarations),
+        Statement::FunctionDefinition { parameters, body, .. } => {
+            fold_parameters(parameters);
+            fold_statements(body);
+        }
+        Statement::If { condition, then_branch, else_branch, .. } => {
+            fold_expression(condition);
+            fold_statements(then_branch);
+            if let Some(else_branch) = else_branch {
+                fold_statements(else_branch);
+            }
+        }
+        Statement::While { condition, body, .. } | Statement::RepeatUntil { condition, body, .. } => {
+            fold_expression(condition);
+            fold_statements(body);
+        }
+        Statement::Block { body, .. } | Statement::Module { body, .. } => fold_statements(body),
+        Statement::Match { scrutinee, cases, default, .. } => {
+            fold_expression(scrutinee);
+            for case in cases {
+                fold_expression(&mut case.value);
+                fold_statements(&mut case.body);
+            }
+            if let Some(default) = default {
+                fold_statements(default);
+            }
+        }
+        Statement::Try { body, catch_body, .. } => {
+            fold_statements(body);
+            fold_statements(catch_body);
+        }
+        Statement::ClassDefinition { fields, methods, .. } => {
+            for default in fields.iter_mut().filter_map(|field| field.default.as_mut()) {
+                fold_expression(default);
+            }
+            for method in methods {
+                fold_parameters(&mut method.parameters);
+                fold_statements(&mut method.body);
+            }
+        }
+        Statement::InterfaceDefinition { methods, .. } => {
+            for method in methods {
+                fold_parameters(&mut method.parameters);
+                if let Some(body) = &mut method.body {
+                    fold_statements(body);
+                }
+            }
+        }
+        Statement::Assignment { target, value, .. } => {
+            fold_expression(target);
+            fold_expression(value);
+        }
+        Statement::Break(_) | Statement::Continue(_) => {}
+    }
+}
+
+fn fold_expression(expression: &mut Expression) {
+    match expression {
+        Expression::Map(entries) => {
+            for (key, value) in entries {
+                fold_expression(key);
+                fold_expression(value);
+            }
+        }
+        Expression::List(elements) => elements.iter_mut().for_each(fold_expression),
+        Expression::Binary { left, operator, right } => {
+            fold_expression(left);
+            fold_expression(right);
+            if let Some(folded) = fold_binary(left, operator, right) {
+                *expression = folded;
+            }
+        }
+        Expression::Unary { operator, operand } => {
+            fold_expression(operand);
+            let folded = match (operator, &**operand) {
+                (UnaryOperator::Negate, Expression::Integer(n)) => n.checked_neg().map(Expression::Integer),
+                (UnaryOperator::Not, Expression::Integer(n)) => Some(boolean(*n == 0)),
+                _ => None,
+            };
+            if let Some(folded) = folded {
+                *expression = folded;
+            }
+        }
+        Expression::FunctionCall { arguments, keyword_arguments, .. } => fold_arguments(arguments, keyword_arguments),
+        Expression::MethodCall { object, arguments, keyword_arguments, .. } => {
+            fold_expression(object);
+            fold_arguments(arguments, keyword_arguments);
+        }
+        Expression::FieldAccess { object, .. } => fold_expression(object),
+        Expression::Index { object, index } => {
+            fold_expression(object);
+            fold_expression(index);
+        }
+        Expression::Integer(_)
+        | Expression::Float(_)
+        | Expression::String(_)
+        | Expression::Char(_)
+        | Expression::Identifier(_)
+        | Expression::ObjectCreation { .. }
+        | Expression::StringInterpolation { .. } => {}
+    }
+}
+
+fn fold_arguments(arguments: &mut [Expression], keyword_arguments: &mut [KeywordArgument]) {
+    arguments.iter_mut().for_each(fold_expression);
+    for argument in keyword_arguments {
+        fold_expression(&mut argument.value);
+    }
+}
+
+/// The literal a binary operation on two literals evaluates to, if it can be
+/// worked out without changing what the program does.
+fn fold_binary(left: &Expression, operator: &BinaryOperator, right: &Expression) -> Option<Expression> {
+    match (left, right) {
+        (Expression::Integer(a), Expression::Integer(b)) => {
+            let (a, b) = (*a, *b);
+            match operator {
+                BinaryOperator::Add => a.checked_add(b).map(Expression::Integer),
+                BinaryOperator::Subtract => a.checked_sub(b).map(Expression::Integer),
+                BinaryOperator::Multiply => a.checked_mul(b).map(Expression::Integer),
+                BinaryOperator::IntDivide => a.checked_div(b).map(Expression::Integer),
+                BinaryOperator::Modulo => a.checked_rem(b).map(Expression::Integer),
+                BinaryOperator::BitAnd => Some(Expression::Integer(a & b)),
+                BinaryOperator::BitOr => Some(Expression::Integer(a | b)),
+                BinaryOperator::BitXor => Some(Expression::Integer(a ^ b)),
+                BinaryOperator::Equal => Some(boolean(a == b)),
+                BinaryOperator::NotEqual => Some(boolean(a != b)),
+                BinaryOperator::Less => Some(boolean(a < b)),
+                BinaryOperator::Greater => Some(boolean(a > b)),
+                BinaryOperator::LessEqual => Some(boolean(a <= b)),
+                BinaryOperator::GreaterEqual => Some(boolean(a >= b)),
+                _ => None,
+            }
+        }
+        // A String literal with `#{` in it is interpolated when it runs, so
+        // neither it nor a concatenation that would create one is folded
+        (Expression::String(a), Expression::String(b)) if a.contains("#{") || b.contains("#{") => None,
+        (Expression::String(a), Expression::String(b)) => match operator {
+            BinaryOperator::Add => Some(format!("{}{}", a, b))
+                .filter(|joined| !joined.contains("#{"))
+                .map(Expression::String),
+            BinaryOperator::Equal => Some(boolean(a == b)),
+            BinaryOperator::NotEqual => Some(boolean(a != b)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn boolean(value: bool) -> Expression {
+    Expression::Integer(if value { 1 } else { 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn optimized(source: &str) -> String {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        formatter::format(&optimize(program))
+    }
+
+    #[test]
+    fn test_folds_constant_subexpressions() {
+        assert_eq!(optimized("Int a = 2 + 3 * 4"), "Int a = 14\n");
+        assert_eq!(optimized("String s = \"a\" + \"b\" + \"c\""), "String s = \"abc\"\n");
+        assert_eq!(optimized("print(-(1 + 1), not 0, 1 < 2 == 1, x + 1 * 2)"), "print(-2, 1, 1, x + 2)\n");
+        assert_eq!(
+            optimized("def f(n: Int = 60 * 60) -> Int\n  n * (2 - 1)\nend"),
+            "def f(n: Int = 3600) -> Int\n  n * 1\nend\n"
+        );
+    }
+
+    #[test]
+    fn test_leaves_operations_that_would_fail() {
+        assert_eq!(optimized("print(1 // 0, 1 % 0)"), "print(1 // 0, 1 % 0)\n");
+        assert_eq!(optimized("print(9223372036854775807 + 1)"), "print(9223372036854775807 + 1)\n");
+        assert_eq!(optimized("print(\"a\" + 1, 1 / 2)"), "print(\"a\" + 1, 1 / 2)\n");
+        // Interpolation happens at run time, so Strings that have or would form `#{` stay as written
+        assert_eq!(optimized("print(\"#{a}\" == \"1\")"), "print(\"#{a}\" == \"1\")\n");
+        assert_eq!(optimized("print(\"#{\" + \"name}\")"), "print(\"#{\" + \"name}\")\n");
+        assert_eq!(optimized("print(\"#\" + \"{name}\")"), "print(\"#\" + \"{name}\")\n");
+    }
+}
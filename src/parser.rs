@@ -1,122 +1,443 @@
+use crate::error::ExlineError;
 use crate::lexer::{Token, TokenType};
+use serde::Serialize;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum Type {
     Int,
+    Float,
     String,
+    Char,
+    Map,
+    List,
+    /// `Map<T>`: a Map whose values are all `T` (keys are always Strings).
+    MapOf(Box<Type>),
+    /// `List<T>`: a List whose elements are all `T`.
+    ListOf(Box<Type>),
+    /// Any function, built-in or user-defined.
+    Function,
     Void,
     Custom(String), // For class types
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Source position where a statement starts, used to locate runtime errors.
+/// Expressions don't carry one: an error is reported at the innermost
+/// statement that was running when it happened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Parameter {
     pub name: String,
     pub param_type: Type,
+    pub default: Option<Expression>,
+    /// A trailing `Type...` parameter collects the remaining arguments into a List.
+    pub variadic: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchCase {
+    pub value: Expression,
+    pub body: Vec<Statement>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct ClassField {
     pub name: String,
     pub field_type: Type,
+    /// Evaluated for each new object; fields without one start at their type's zero value.
+    pub default: Option<Expression>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Method {
     pub name: String,
+    pub is_static: bool,
     pub parameters: Vec<Parameter>,
     pub return_type: Type,
     pub body: Vec<Statement>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct InterfaceMethod {
     pub name: String,
     pub parameters: Vec<Parameter>,
     pub return_type: Type,
+    /// A default body, used by implementing classes that don't define the method.
+    pub body: Option<Vec<Statement>>,
+}
+
+/// `name: value` in a call's argument list.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeywordArgument {
+    pub name: String,
+    pub value: Expression,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum Expression {
     Integer(i64),
+    Float(f64),
     String(String),
+    Char(char),
+    Map(Vec<(Expression, Expression)>),
+    List(Vec<Expression>),
     Identifier(String),
     Binary {
         left: Box<Expression>,
         operator: BinaryOperator,
         right: Box<Expression>,
     },
+    Unary {
+        operator: UnaryOperator,
+        operand: Box<Expression>,
+    },
     FunctionCall {
         name: String,
         arguments: Vec<Expression>,
+        keyword_arguments: Vec<KeywordArgument>,
     },
+    /// `optional` is set for `?.`, which yields void instead of failing when
+    /// the receiver is void.
     MethodCall {
         object: Box<Expression>,
         method: String,
         arguments: Vec<Expression>,
+        keyword_arguments: Vec<KeywordArgument>,
+        optional: bool,
     },
     FieldAccess {
         object: Box<Expression>,
         field: String,
+        optional: bool,
+    },
+    Index {
+        object: Box<Expression>,
+        index: Box<Expression>,
     },
     ObjectCreation {
         class_name: String,
     },
+    #[allow(dead_code)] // Interpolation is currently resolved by the interpreter
     StringInterpolation {
         parts: Vec<StringPart>,
     },
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum StringPart {
     Literal(String),
     Expression(Expression),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum BinaryOperator {
     Add,
     Subtract,
     Multiply,
     Divide,
+    IntDivide,
+    Modulo,
     Equal,
+    NotEqual,
+    And,
+    Or,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Power,
+}
+
+/// How a chain of operators with the same precedence groups: `a - b - c` is
+/// `(a - b) - c` (left), while `a ** b ** c` is `a ** (b ** c)` (right).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+/// Precedence of the prefix `not`, between `and` and `==`.
+pub const NOT_PRECEDENCE: u8 = 3;
+
+/// How deeply expressions and blocks may nest, counted together. The parser
+/// recurses once per level, so without a limit deeply nested input would
+/// overflow the stack instead of failing with an error.
+pub const MAX_NESTING_DEPTH: usize = 128;
+
+impl BinaryOperator {
+    /// How tightly the operator binds; higher binds tighter.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Equal | BinaryOperator::NotEqual => 4,
+            BinaryOperator::Less
+            | BinaryOperator::Greater
+            | BinaryOperator::LessEqual
+            | BinaryOperator::GreaterEqual => 5,
+            BinaryOperator::BitOr => 6,
+            BinaryOperator::BitXor => 7,
+            BinaryOperator::BitAnd => 8,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => 9,
+            BinaryOperator::Add | BinaryOperator::Subtract => 10,
+            BinaryOperator::Multiply
+            | BinaryOperator::Divide
+            | BinaryOperator::IntDivide
+            | BinaryOperator::Modulo => 11,
+            BinaryOperator::Power => 12,
+        }
+    }
+
+    pub fn associativity(&self) -> Associativity {
+        match self {
+            BinaryOperator::Power => Associativity::Right,
+            _ => Associativity::Left,
+        }
+    }
+}
+
+/// The binary operator a token stands for, if any.
+fn binary_operator(token_type: &TokenType) -> Option<BinaryOperator> {
+    Some(match token_type {
+        TokenType::Or => BinaryOperator::Or,
+        TokenType::And => BinaryOperator::And,
+        TokenType::Equal => BinaryOperator::Equal,
+        TokenType::NotEqual => BinaryOperator::NotEqual,
+        TokenType::Less => BinaryOperator::Less,
+        TokenType::Greater => BinaryOperator::Greater,
+        TokenType::LessEqual => BinaryOperator::LessEqual,
+        TokenType::GreaterEqual => BinaryOperator::GreaterEqual,
+        TokenType::Pipe => BinaryOperator::BitOr,
+        TokenType::Caret => BinaryOperator::BitXor,
+        TokenType::Ampersand => BinaryOperator::BitAnd,
+        TokenType::ShiftLeft => BinaryOperator::ShiftLeft,
+        TokenType::ShiftRight => BinaryOperator::ShiftRight,
+        TokenType::Plus => BinaryOperator::Add,
+        TokenType::Minus => BinaryOperator::Subtract,
+        TokenType::Multiply => BinaryOperator::Multiply,
+        TokenType::Divide => BinaryOperator::Divide,
+        TokenType::IntDivide => BinaryOperator::IntDivide,
+        TokenType::Modulo => BinaryOperator::Modulo,
+        TokenType::Power => BinaryOperator::Power,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum Statement {
     VariableDeclaration {
         name: String,
         var_type: Type,
         value: Expression,
+        span: Span,
+    },
+    ConstDeclaration {
+        name: String,
+        var_type: Type,
+        value: Expression,
+        span: Span,
+    },
+    /// `Int a = 1, b = 2`: a declaration (or constant declaration) per name.
+    MultipleDeclaration {
+        declarations: Vec<Statement>,
+        span: Span,
     },
     FunctionDefinition {
         name: String,
         parameters: Vec<Parameter>,
         return_type: Type,
         body: Vec<Statement>,
+        span: Span,
     },
     If {
         condition: Expression,
         then_branch: Vec<Statement>,
         else_branch: Option<Vec<Statement>>,
+        span: Span,
+    },
+    While {
+        condition: Expression,
+        body: Vec<Statement>,
+        span: Span,
     },
+    /// `repeat ... until condition`: the body runs once before the condition
+    /// is first checked, and again each time it is falsy.
+    RepeatUntil {
+        body: Vec<Statement>,
+        condition: Expression,
+        span: Span,
+    },
+    /// `do ... end`: statements run in their own scope.
+    Block {
+        body: Vec<Statement>,
+        span: Span,
+    },
+    Match {
+        scrutinee: Expression,
+        cases: Vec<MatchCase>,
+        default: Option<Vec<Statement>>,
+        span: Span,
+    },
+    Try {
+        body: Vec<Statement>,
+        catch_var: String,
+        catch_body: Vec<Statement>,
+        span: Span,
+    },
+    Raise {
+        message: Expression,
+        span: Span,
+    },
+    Return {
+        value: Option<Expression>,
+        span: Span,
+    },
+    Break(Span),
+    Continue(Span),
     ClassDefinition {
         name: String,
-        implements: Option<String>,
+        implements: Vec<String>,
         fields: Vec<ClassField>,
         methods: Vec<Method>,
+        span: Span,
     },
     InterfaceDefinition {
         name: String,
         methods: Vec<InterfaceMethod>,
+        span: Span,
+    },
+    /// `module Name ... end`: the classes, interfaces, functions and modules
+    /// defined inside are named `Name.Inner`.
+    Module {
+        name: String,
+        body: Vec<Statement>,
+        span: Span,
     },
     Assignment {
         target: Expression,
         value: Expression,
+        span: Span,
     },
-    Expression(Expression),
+    Expression(Expression, Span),
+}
+
+impl Expression {
+    /// The dotted name an identifier or chain of field accesses spells out,
+    /// such as `Geo.Point`.
+    pub fn dotted_name(&self) -> Option<String> {
+        match self {
+            Expression::Identifier(name) => Some(name.clone()),
+            Expression::FieldAccess { object, field, optional: false } => {
+                object.dotted_name().map(|prefix| format!("{}.{}", prefix, field))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::VariableDeclaration { span, .. }
+            | Statement::ConstDeclaration { span, .. }
+            | Statement::MultipleDeclaration { span, .. }
+            | Statement::FunctionDefinition { span, .. }
+            | Statement::If { span, .. }
+            | Statement::While { span, .. }
+            | Statement::RepeatUntil { span, .. }
+            | Statement::Block { span, .. }
+            | Statement::Match { span, .. }
+            | Statement::Try { span, .. }
+            | Statement::Raise { span, .. }
+            | Statement::Return { span, .. }
+            | Statement::ClassDefinition { span, .. }
+            | Statement::InterfaceDefinition { span, .. }
+            | Statement::Module { span, .. }
+            | Statement::Assignment { span, .. }
+            | Statement::Break(span)
+            | Statement::Continue(span)
+            | Statement::Expression(_, span) => *span,
+        }
+    }
+}
+
+/// Whether running `body` always ends with a value: a `return value`, a
+/// `raise`, or an expression statement (whose value is only known at run
+/// time), on every path through it.
+fn always_returns(body: &[Statement]) -> bool {
+    body.iter().any(|statement| ends_with_value(statement, true))
+}
+
+/// Whether `statement` always ends with a value. An expression statement
+/// only counts when `expressions` is set: a loop body discards their values,
+/// so only `return` and `raise` can end it.
+fn ends_with_value(statement: &Statement, expressions: bool) -> bool {
+    match statement {
+        Statement::Return { value, .. } => value.is_some(),
+        Statement::Raise { .. } => true,
+        Statement::Expression(..) => expressions,
+        Statement::If { then_branch, else_branch: Some(else_branch), .. } => {
+            block_ends_with_value(then_branch, expressions) && block_ends_with_value(else_branch, expressions)
+        }
+        Statement::Match { cases, default: Some(default), .. } => {
+            cases.iter().all(|case| block_ends_with_value(&case.body, expressions))
+                && block_ends_with_value(default, expressions)
+        }
+        Statement::Block { body, .. } => block_ends_with_value(body, expressions),
+        Statement::Try { body, catch_body, .. } => {
+            block_ends_with_value(body, expressions) && block_ends_with_value(catch_body, expressions)
+        }
+        // `while 1` only ends through a break; otherwise it runs forever or returns
+        Statement::While { condition: Expression::Integer(n), body, .. } => *n != 0 && !breaks(body),
+        Statement::RepeatUntil { body, .. } => block_ends_with_value(body, false) && !breaks(body),
+        _ => false,
+    }
+}
+
+/// Whether a block always ends with a value. Only its last statement's value
+/// is the block's result; values from earlier statements are discarded.
+fn block_ends_with_value(body: &[Statement], expressions: bool) -> bool {
+    match body.split_last() {
+        Some((last, rest)) => {
+            rest.iter().any(|statement| ends_with_value(statement, false)) || ends_with_value(last, expressions)
+        }
+        None => false,
+    }
+}
+
+/// Whether `body` contains a `break` for the loop it belongs to.
+fn breaks(body: &[Statement]) -> bool {
+    body.iter().any(|statement| match statement {
+        Statement::Break(_) => true,
+        Statement::If { then_branch, else_branch, .. } => {
+            breaks(then_branch) || else_branch.as_deref().is_some_and(breaks)
+        }
+        Statement::Match { cases, default, .. } => {
+            cases.iter().any(|case| breaks(&case.body)) || default.as_deref().is_some_and(breaks)
+        }
+        Statement::Block { body, .. } => breaks(body),
+        Statement::Try { body, catch_body, .. } => breaks(body) || breaks(catch_body),
+        _ => false,
+    })
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Serialize)]
 pub struct Program {
     pub statements: Vec<Statement>,
 }
@@ -124,43 +445,170 @@ pub struct Program {
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    errors: Vec<ExlineError>,
+    /// Number of loops enclosing the current statement, reset inside
+    /// function and method bodies.
+    loop_depth: usize,
+    /// Number of function and method bodies enclosing the current statement.
+    function_depth: usize,
+    /// Number of expressions and statements being parsed around the
+    /// current token, kept below `MAX_NESTING_DEPTH`.
+    nesting_depth: usize,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+    /// Creates a parser for `tokens`, adding an `Eof` if they don't end with
+    /// one, so there is always a current token to look at.
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        if tokens.last().is_none_or(|token| token.token_type != TokenType::Eof) {
+            let (line, column) = tokens.last().map_or((1, 1), |token| (token.end_line, token.end_column));
+            tokens.push(Token::new(TokenType::Eof, line, column));
+        }
+        Self {
+            tokens,
+            current: 0,
+            errors: Vec::new(),
+            loop_depth: 0,
+            function_depth: 0,
+            nesting_depth: 0,
+        }
     }
 
-    pub fn parse(&mut self) -> Result<Program, String> {
+    /// Parses the whole program. Statement-level errors are collected rather
+    /// than aborting, so every error in the file is reported at once.
+    pub fn parse(&mut self) -> Result<Program, Vec<ExlineError>> {
         let mut statements = Vec::new();
 
         while !self.is_at_end() {
             // Skip newlines at the top level
-            if self.check(&TokenType::Newline) {
+            if self.check_terminator() {
                 self.advance();
                 continue;
             }
 
-            statements.push(self.statement()?);
+            if let Some(statement) = self.statement_or_recover() {
+                statements.push(statement);
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(Program { statements })
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// Parses a statement, recording any error and skipping ahead so parsing
+    /// can continue with the next statement.
+    fn statement_or_recover(&mut self) -> Option<Statement> {
+        let start = self.current;
+        match self.statement() {
+            Ok(statement) => Some(statement),
+            Err(message) => {
+                // Point at the token that was just rejected when the parser has
+                // already moved past it onto a line break or the end of input
+                let token = if self.current > 0
+                    && (self.at_line_start() || self.check_terminator() || self.is_at_end())
+                {
+                    self.previous()
+                } else {
+                    self.peek()
+                };
+                self.errors.push(ExlineError::new(message, token.line, token.column));
+
+                // Always make progress, even if the statement failed on its first token
+                if self.current == start {
+                    self.advance();
+                }
+                self.synchronize();
+                None
+            }
         }
+    }
 
-        Ok(Program { statements })
+    /// Skips to the start of the next line, stopping early at an `end` so the
+    /// enclosing block can still be closed.
+    fn synchronize(&mut self) {
+        if self.at_line_start() {
+            return;
+        }
+        while !self.is_at_end() {
+            if self.check_terminator() {
+                self.advance();
+                return;
+            }
+            if self.check(&TokenType::End) {
+                return;
+            }
+            self.advance();
+        }
     }
 
     fn statement(&mut self) -> Result<Statement, String> {
-        if self.check(&TokenType::Int) || self.check(&TokenType::String_) {
+        self.nested("Blocks", Self::statement_inner)
+    }
+
+    /// Runs `parse` one nesting level deeper, failing instead if `kind`
+    /// (such as blocks or an expression) would nest too deeply.
+    fn nested<T>(&mut self, kind: &str, parse: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        if self.nesting_depth >= MAX_NESTING_DEPTH {
+            return Err(format!("{} nested too deeply", kind));
+        }
+        self.nesting_depth += 1;
+        let result = parse(self);
+        self.nesting_depth -= 1;
+        result
+    }
+
+    fn statement_inner(&mut self) -> Result<Statement, String> {
+        if self.check(&TokenType::Int)
+            || self.check(&TokenType::Float_)
+            || self.check(&TokenType::String_)
+            || self.check(&TokenType::Char_)
+            || self.check(&TokenType::Map)
+            || self.check(&TokenType::List)
+            || self.check(&TokenType::Function)
+        {
             // Check if it's a variable declaration or custom type
             self.variable_or_custom_declaration()
+        } else if self.is_custom_declaration() {
+            // `ClassName name = ...`
+            self.variable_or_custom_declaration()
+        } else if self.check(&TokenType::Const) {
+            self.const_declaration()
         } else if self.check(&TokenType::Def) {
             self.function_definition()
         } else if self.check(&TokenType::If) {
             self.if_statement()
+        } else if self.check(&TokenType::While) {
+            self.while_statement()
+        } else if self.check(&TokenType::Repeat) {
+            self.repeat_statement()
+        } else if self.check(&TokenType::Do) {
+            self.block_statement()
+        } else if self.check(&TokenType::Try) {
+            self.try_statement()
+        } else if self.check(&TokenType::Return) {
+            self.return_statement()
+        } else if self.check(&TokenType::Raise) {
+            let span = self.current_span();
+            self.advance();
+            let message = self.expression()?;
+            self.consume_newline_or_eof()?;
+            Ok(Statement::Raise { message, span })
+        } else if self.check(&TokenType::Match) {
+            self.match_statement()
+        } else if self.check(&TokenType::Break) || self.check(&TokenType::Continue) {
+            self.loop_control()
         } else if self.check(&TokenType::Class) {
             self.class_definition()
         } else if self.check(&TokenType::Interface) {
             self.interface_definition()
+        } else if self.check(&TokenType::Module) {
+            self.module_definition()
         } else {
             // Check if it's an assignment or expression
+            let span = self.current_span();
             let expr = self.expression()?;
 
             // Check if this is an assignment (field assignment)
@@ -171,51 +619,108 @@ impl Parser {
                 Ok(Statement::Assignment {
                     target: expr,
                     value,
+                    span,
                 })
             } else {
                 self.consume_newline_or_eof()?;
-                Ok(Statement::Expression(expr))
+                Ok(Statement::Expression(expr, span))
+            }
+        }
+    }
+
+    /// Whether the next tokens are a type name, possibly qualified as in
+    /// `Geo.Point`, followed by a variable name.
+    fn is_custom_declaration(&self) -> bool {
+        let token_type = |offset: usize| self.tokens.get(self.current + offset).map(|token| &token.token_type);
+
+        let mut offset = 0;
+        loop {
+            if !matches!(token_type(offset), Some(TokenType::Identifier(_))) {
+                return false;
+            }
+            match token_type(offset + 1) {
+                Some(TokenType::Dot) => offset += 2,
+                Some(TokenType::Identifier(_)) => return true,
+                _ => return false,
             }
         }
     }
 
+    /// Parses `Type name = value`, or `Type a = 1, b = 2` which declares each
+    /// name with the same type.
     fn variable_or_custom_declaration(&mut self) -> Result<Statement, String> {
-        let var_type = if self.check(&TokenType::Int) {
-            self.advance();
-            Type::Int
-        } else if self.check(&TokenType::String_) {
+        let span = self.current_span();
+        let var_type = self.parse_type()?;
+
+        let mut declarations = Vec::new();
+        loop {
+            let declaration_span = self.current_span();
+            let name = if let TokenType::Identifier(name) = &self.advance().token_type {
+                name.clone()
+            } else {
+                return Err("Expected identifier".to_string());
+            };
+
+            if !self.check(&TokenType::Assign) {
+                return Err("Expected '='".to_string());
+            }
             self.advance();
-            Type::String
-        } else if let TokenType::Identifier(type_name) = &self.peek().token_type {
-            let type_name = type_name.clone();
+
+            let value = self.expression()?;
+            declarations.push(Statement::VariableDeclaration {
+                name,
+                var_type: var_type.clone(),
+                value,
+                span: if declarations.is_empty() { span } else { declaration_span },
+            });
+
+            if !self.check(&TokenType::Comma) {
+                break;
+            }
             self.advance();
-            Type::Custom(type_name)
-        } else {
-            return Err("Expected type".to_string());
-        };
+        }
+        self.consume_newline_or_eof()?;
 
-        let name = if let TokenType::Identifier(name) = &self.advance().token_type {
-            name.clone()
+        if declarations.len() == 1 {
+            Ok(declarations.remove(0))
         } else {
-            return Err("Expected identifier".to_string());
-        };
-
-        if !self.check(&TokenType::Assign) {
-            return Err("Expected '='".to_string());
+            Ok(Statement::MultipleDeclaration { declarations, span })
         }
-        self.advance();
+    }
 
-        let value = self.expression()?;
-        self.consume_newline_or_eof()?;
+    fn const_declaration(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
+        self.consume(&TokenType::Const, "Expected 'const'")?;
 
-        Ok(Statement::VariableDeclaration {
-            name,
-            var_type,
-            value,
-        })
+        fn into_constant(declaration: Statement, span: Span) -> Statement {
+            match declaration {
+                Statement::VariableDeclaration { name, var_type, value, .. } => Statement::ConstDeclaration {
+                    name,
+                    var_type,
+                    value,
+                    span,
+                },
+                _ => unreachable!("variable_or_custom_declaration always returns declarations"),
+            }
+        }
+
+        match self.variable_or_custom_declaration()? {
+            Statement::MultipleDeclaration { declarations, .. } => Ok(Statement::MultipleDeclaration {
+                declarations: declarations
+                    .into_iter()
+                    .map(|declaration| {
+                        let declaration_span = declaration.span();
+                        into_constant(declaration, declaration_span)
+                    })
+                    .collect(),
+                span,
+            }),
+            declaration => Ok(into_constant(declaration, span)),
+        }
     }
 
     fn function_definition(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
         self.consume(&TokenType::Def, "Expected 'def'")?;
 
         let name = if let TokenType::Identifier(name) = &self.advance().token_type {
@@ -226,291 +731,540 @@ impl Parser {
 
         self.consume(&TokenType::LeftParen, "Expected '('")?;
 
-        let mut parameters = Vec::new();
-        if !self.check(&TokenType::RightParen) {
-            loop {
-                let param_name = if let TokenType::Identifier(name) = &self.advance().token_type {
-                    name.clone()
-                } else {
-                    return Err("Expected parameter name".to_string());
-                };
-
-                // Expect colon
-                self.consume(&TokenType::Colon, "Expected ':' after parameter name")?;
-
-                let param_type = if self.check(&TokenType::Int) {
-                    self.advance();
-                    Type::Int
-                } else if self.check(&TokenType::String_) {
-                    self.advance();
-                    Type::String
-                } else {
-                    return Err("Expected parameter type".to_string());
-                };
-
-                parameters.push(Parameter {
-                    name: param_name,
-                    param_type,
-                });
-
-                if self.check(&TokenType::RightParen) {
-                    break;
-                }
-                // Skip any commas or other separators
-                while !self.check(&TokenType::Identifier("".to_string())) && !self.check(&TokenType::RightParen) && !self.is_at_end() {
-                    if let TokenType::Identifier(_) = self.peek().token_type {
-                        break;
-                    }
-                    self.advance();
-                }
-            }
-        }
+        let parameters = self.parameter_list()?;
 
         self.consume(&TokenType::RightParen, "Expected ')'")?;
         self.consume(&TokenType::Arrow, "Expected '->'")?;
 
-        let return_type = if self.check(&TokenType::Int) {
-            self.advance();
-            Type::Int
-        } else if self.check(&TokenType::String_) {
-            self.advance();
-            Type::String
-        } else {
-            return Err("Expected return type".to_string());
-        };
-
-        self.consume_newline_or_eof()?;
-
-        let mut body = Vec::new();
-        while !self.check(&TokenType::End) && !self.is_at_end() {
-            if self.check(&TokenType::Newline) {
-                self.advance();
-                continue;
-            }
-            body.push(self.statement()?);
-        }
+        let return_type = self.parse_type()?;
 
-        self.consume(&TokenType::End, "Expected 'end'")?;
-        self.consume_newline_or_eof()?;
+        let braced = self.open_block()?;
+        let body = self.function_body(braced)?;
+        self.check_returns("Function", &name, &return_type, &body, span);
 
         Ok(Statement::FunctionDefinition {
             name,
             parameters,
             return_type,
             body,
+            span,
         })
     }
 
     fn if_statement(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
         self.consume(&TokenType::If, "Expected 'if'")?;
 
         let condition = self.expression()?;
+        let braced = self.open_block()?;
+        let statement = self.if_branches(span, condition, braced)?;
+
+        // Braced branches each close their own block
+        if !braced {
+            self.close_block(false)?;
+        }
         self.consume_newline_or_eof()?;
+        Ok(statement)
+    }
 
-        let mut then_branch = Vec::new();
-        while !self.check(&TokenType::Else) && !self.check(&TokenType::End) && !self.is_at_end() {
-            if self.check(&TokenType::Newline) {
-                self.advance();
-                continue;
-            }
-            then_branch.push(self.statement()?);
+    /// Parses the branches after an `if` or `elif` condition. With `end`
+    /// blocks, one `end` after the last branch closes the whole chain; with
+    /// braces, every branch is closed by its own `}`, and `elif` or `else`
+    /// follows it on the same line. An `elif` becomes an `if` nested as the
+    /// only statement of the else branch.
+    fn if_branches(&mut self, span: Span, condition: Expression, braced: bool) -> Result<Statement, String> {
+        let then_branch = self.block_statements(&[TokenType::Else, TokenType::Elif]);
+        if braced {
+            self.close_block(true)?;
         }
 
-        let else_branch = if self.check(&TokenType::Else) {
+        let else_branch = if self.check(&TokenType::Elif) {
+            let elif_span = self.current_span();
             self.advance();
-            self.consume_newline_or_eof()?;
+            let condition = self.expression()?;
+            self.open_branch(braced)?;
+            Some(vec![self.if_branches(elif_span, condition, braced)?])
+        } else if self.check(&TokenType::Else) {
+            self.advance();
+            self.open_branch(braced)?;
 
-            let mut else_statements = Vec::new();
-            while !self.check(&TokenType::End) && !self.is_at_end() {
-                if self.check(&TokenType::Newline) {
-                    self.advance();
-                    continue;
-                }
-                else_statements.push(self.statement()?);
+            let else_statements = self.block_statements(&[TokenType::Elif]);
+            if self.check(&TokenType::Elif) {
+                self.advance();
+                return Err("'elif' must come before 'else'".to_string());
+            }
+            if braced {
+                self.close_block(true)?;
             }
             Some(else_statements)
         } else {
             None
         };
 
-        self.consume(&TokenType::End, "Expected 'end'")?;
-        self.consume_newline_or_eof()?;
-
         Ok(Statement::If {
             condition,
             then_branch,
             else_branch,
+            span,
         })
     }
 
-    fn class_definition(&mut self) -> Result<Statement, String> {
-        self.consume(&TokenType::Class, "Expected 'class'")?;
+    /// Opens an `elif` or `else` branch in the same style as the `if`.
+    fn open_branch(&mut self, braced: bool) -> Result<(), String> {
+        if self.open_block()? != braced {
+            return Err("Every branch of an if must use '{' if the first one does, and none otherwise".to_string());
+        }
+        Ok(())
+    }
 
-        let name = if let TokenType::Identifier(name) = &self.advance().token_type {
-            name.clone()
-        } else {
-            return Err("Expected class name".to_string());
-        };
+    fn while_statement(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
+        self.consume(&TokenType::While, "Expected 'while'")?;
 
-        // Check for implements clause
-        let implements = if self.check(&TokenType::Implements) {
-            self.advance();
-            if let TokenType::Identifier(interface_name) = &self.advance().token_type {
-                Some(interface_name.clone())
-            } else {
-                return Err("Expected interface name after 'implements'".to_string());
-            }
-        } else {
-            None
-        };
+        let condition = self.expression()?;
+        let braced = self.open_block()?;
+
+        self.loop_depth += 1;
+        let body = self.block_statements(&[]);
+        self.loop_depth -= 1;
 
+        self.close_block(braced)?;
         self.consume_newline_or_eof()?;
 
-        let mut fields = Vec::new();
-        let mut methods = Vec::new();
+        Ok(Statement::While {
+            condition,
+            body,
+            span,
+        })
+    }
 
-        while !self.check(&TokenType::End) && !self.is_at_end() {
-            if self.check(&TokenType::Newline) {
-                self.advance();
-                continue;
-            }
+    fn repeat_statement(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
+        self.consume(&TokenType::Repeat, "Expected 'repeat'")?;
+        self.consume_newline_or_eof()?;
 
-            if self.check(&TokenType::Def) {
-                // Parse method
-                methods.push(self.parse_method()?);
-            } else {
-                // Parse field
-                fields.push(self.parse_field()?);
-            }
-        }
+        self.loop_depth += 1;
+        let body = self.block_statements(&[TokenType::Until]);
+        self.loop_depth -= 1;
 
-        self.consume(&TokenType::End, "Expected 'end'")?;
+        self.consume(&TokenType::Until, "Expected 'until' to close 'repeat'")?;
+        let condition = self.expression()?;
         self.consume_newline_or_eof()?;
 
-        Ok(Statement::ClassDefinition {
-            name,
-            implements,
-            fields,
-            methods,
+        Ok(Statement::RepeatUntil {
+            body,
+            condition,
+            span,
         })
     }
 
-    fn interface_definition(&mut self) -> Result<Statement, String> {
-        self.consume(&TokenType::Interface, "Expected 'interface'")?;
+    fn block_statement(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
+        self.consume(&TokenType::Do, "Expected 'do'")?;
+        self.consume_newline_or_eof()?;
 
-        let name = if let TokenType::Identifier(name) = &self.advance().token_type {
-            name.clone()
-        } else {
-            return Err("Expected interface name".to_string());
-        };
+        let body = self.block_statements(&[]);
 
+        self.close_block(false)?;
         self.consume_newline_or_eof()?;
 
-        let mut methods = Vec::new();
+        Ok(Statement::Block { body, span })
+    }
 
-        while !self.check(&TokenType::End) && !self.is_at_end() {
-            if self.check(&TokenType::Newline) {
-                self.advance();
-                continue;
-            }
+    fn try_statement(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
+        self.consume(&TokenType::Try, "Expected 'try'")?;
+        self.consume_newline_or_eof()?;
 
-            methods.push(self.parse_interface_method()?);
-        }
+        let body = self.block_statements(&[TokenType::Catch]);
 
-        self.consume(&TokenType::End, "Expected 'end'")?;
+        self.consume(&TokenType::Catch, "Expected 'catch'")?;
+        let catch_var = if let TokenType::Identifier(name) = &self.advance().token_type {
+            name.clone()
+        } else {
+            return Err("Expected variable name after 'catch'".to_string());
+        };
         self.consume_newline_or_eof()?;
 
-        Ok(Statement::InterfaceDefinition {
-            name,
-            methods,
+        let catch_body = self.block_statements(&[]);
+
+        self.close_block(false)?;
+        self.consume_newline_or_eof()?;
+
+        Ok(Statement::Try {
+            body,
+            catch_var,
+            catch_body,
+            span,
         })
     }
 
-    fn parse_field(&mut self) -> Result<ClassField, String> {
-        let field_type = self.parse_type()?;
+    fn match_statement(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
+        self.consume(&TokenType::Match, "Expected 'match'")?;
 
-        let name = if let TokenType::Identifier(name) = &self.advance().token_type {
-            name.clone()
+        let scrutinee = self.expression()?;
+        self.consume_newline_or_eof()?;
+        self.skip_newlines();
+
+        let mut cases = Vec::new();
+        while self.check(&TokenType::Case) {
+            self.advance();
+            let value = self.expression()?;
+            self.consume_newline_or_eof()?;
+            let body = self.case_body();
+            cases.push(MatchCase { value, body });
+        }
+
+        let default = if self.check(&TokenType::Default) {
+            self.advance();
+            self.consume_newline_or_eof()?;
+            Some(self.case_body())
         } else {
-            return Err("Expected field name".to_string());
+            None
         };
 
+        if !self.check(&TokenType::End) {
+            return Err("Expected 'case', 'default' or 'end' in match".to_string());
+        }
+        self.consume(&TokenType::End, "Expected 'end'")?;
         self.consume_newline_or_eof()?;
 
-        Ok(ClassField {
-            name,
-            field_type,
+        Ok(Statement::Match {
+            scrutinee,
+            cases,
+            default,
+            span,
         })
     }
 
-    fn parse_method(&mut self) -> Result<Method, String> {
-        self.consume(&TokenType::Def, "Expected 'def'")?;
+    /// Collects the statements of a `case` or `default` arm up to the next arm
+    /// or the closing `end`.
+    fn case_body(&mut self) -> Vec<Statement> {
+        self.block_statements(&[TokenType::Case, TokenType::Default])
+    }
 
-        let name = if let TokenType::Identifier(name) = &self.advance().token_type {
-            name.clone()
+    /// Parses `return` or `return value`, which are only valid inside a
+    /// function or method body.
+    fn return_statement(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
+        self.consume(&TokenType::Return, "Expected 'return'")?;
+
+        if self.function_depth == 0 {
+            return Err("'return' outside of a function".to_string());
+        }
+
+        let value = if self.check_terminator() || self.check(&TokenType::End) || self.is_at_end() {
+            None
         } else {
-            return Err("Expected method name".to_string());
+            Some(self.expression()?)
         };
+        self.consume_newline_or_eof()?;
 
-        self.consume(&TokenType::LeftParen, "Expected '('")?;
+        Ok(Statement::Return { value, span })
+    }
 
-        let mut parameters = Vec::new();
-        if !self.check(&TokenType::RightParen) {
-            loop {
-                let param_name = if let TokenType::Identifier(name) = &self.advance().token_type {
-                    name.clone()
-                } else {
-                    return Err("Expected parameter name".to_string());
-                };
+    /// Parses `break` or `continue`, which are only valid inside a loop.
+    fn loop_control(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
+        let is_break = self.check(&TokenType::Break);
+        let keyword = if is_break { "break" } else { "continue" };
+        self.advance();
 
-                self.consume(&TokenType::Colon, "Expected ':' after parameter name")?;
-                let param_type = self.parse_type()?;
+        if self.loop_depth == 0 {
+            return Err(format!("'{}' outside of a loop", keyword));
+        }
+        self.consume_newline_or_eof()?;
 
-                parameters.push(Parameter {
-                    name: param_name,
-                    param_type,
-                });
+        if is_break {
+            Ok(Statement::Break(span))
+        } else {
+            Ok(Statement::Continue(span))
+        }
+    }
+
+    fn class_definition(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
+        self.consume(&TokenType::Class, "Expected 'class'")?;
 
-                if self.check(&TokenType::RightParen) {
+        let name = if let TokenType::Identifier(name) = &self.advance().token_type {
+            name.clone()
+        } else {
+            return Err("Expected class name".to_string());
+        };
+
+        // Check for implements clause: `implements A, B`
+        let mut implements = Vec::new();
+        if self.check(&TokenType::Implements) {
+            self.advance();
+            loop {
+                if !matches!(self.peek().token_type, TokenType::Identifier(_)) {
+                    return Err("Expected interface name after 'implements'".to_string());
+                }
+                implements.push(self.qualified_name()?);
+                if !self.check(&TokenType::Comma) {
                     break;
                 }
-                // Skip commas or other separators
-                while !self.check(&TokenType::Identifier("".to_string())) && !self.check(&TokenType::RightParen) && !self.is_at_end() {
-                    if let TokenType::Identifier(_) = self.peek().token_type {
-                        break;
+                self.advance();
+            }
+        }
+
+        let braced = self.open_block()?;
+
+        let mut fields = Vec::new();
+        let mut methods = Vec::new();
+
+        while !self.at_block_close() && !self.is_at_end() {
+            if self.check_terminator() {
+                self.advance();
+                continue;
+            }
+
+            if self.check(&TokenType::Static) {
+                // Parse static method
+                self.advance();
+                methods.push(self.parse_method(true)?);
+            } else if self.check(&TokenType::Def) {
+                // Parse method
+                methods.push(self.parse_method(false)?);
+            } else {
+                // Parse field
+                fields.push(self.parse_field()?);
+            }
+        }
+
+        self.close_block(braced)?;
+        self.consume_newline_or_eof()?;
+
+        Ok(Statement::ClassDefinition {
+            name,
+            implements,
+            fields,
+            methods,
+            span,
+        })
+    }
+
+    fn module_definition(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
+        self.consume(&TokenType::Module, "Expected 'module'")?;
+
+        let name = if let TokenType::Identifier(name) = &self.advance().token_type {
+            name.clone()
+        } else {
+            return Err("Expected module name".to_string());
+        };
+
+        self.consume_newline_or_eof()?;
+
+        let mut body = Vec::new();
+        while !self.check(&TokenType::End) && !self.is_at_end() {
+            if self.check_terminator() {
+                self.advance();
+                continue;
+            }
+            if let Some(statement) = self.statement_or_recover() {
+                match statement {
+                    Statement::ClassDefinition { .. }
+                    | Statement::InterfaceDefinition { .. }
+                    | Statement::FunctionDefinition { .. }
+                    | Statement::Module { .. } => body.push(statement),
+                    other => {
+                        let span = other.span();
+                        self.errors.push(ExlineError::new(
+                            "Only classes, interfaces, functions and modules can be defined in a module",
+                            span.line,
+                            span.column,
+                        ));
                     }
-                    self.advance();
                 }
             }
         }
 
-        self.consume(&TokenType::RightParen, "Expected ')'")?;
-        self.consume(&TokenType::Colon, "Expected ':' before return type")?;
-        let return_type = self.parse_type()?;
+        self.consume(&TokenType::End, "Expected 'end'")?;
+        self.consume_newline_or_eof()?;
+
+        Ok(Statement::Module { name, body, span })
+    }
+
+    fn interface_definition(&mut self) -> Result<Statement, String> {
+        let span = self.current_span();
+        self.consume(&TokenType::Interface, "Expected 'interface'")?;
+
+        let name = if let TokenType::Identifier(name) = &self.advance().token_type {
+            name.clone()
+        } else {
+            return Err("Expected interface name".to_string());
+        };
 
         self.consume_newline_or_eof()?;
 
-        let mut body = Vec::new();
+        let mut methods = Vec::new();
+
         while !self.check(&TokenType::End) && !self.is_at_end() {
-            if self.check(&TokenType::Newline) {
+            if self.check_terminator() {
                 self.advance();
                 continue;
             }
-            body.push(self.statement()?);
+
+            methods.push(self.parse_interface_method()?);
         }
 
         self.consume(&TokenType::End, "Expected 'end'")?;
         self.consume_newline_or_eof()?;
 
+        Ok(Statement::InterfaceDefinition {
+            name,
+            methods,
+            span,
+        })
+    }
+
+    fn parse_field(&mut self) -> Result<ClassField, String> {
+        let field_type = self.parse_type()?;
+
+        let name = if let TokenType::Identifier(name) = &self.advance().token_type {
+            name.clone()
+        } else {
+            return Err("Expected field name".to_string());
+        };
+
+        let default = if self.check(&TokenType::Assign) {
+            self.advance();
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume_newline_or_eof()?;
+
+        Ok(ClassField {
+            name,
+            field_type,
+            default,
+        })
+    }
+
+    fn parse_method(&mut self, is_static: bool) -> Result<Method, String> {
+        let span = self.current_span();
+        let message = if is_static { "Expected 'def' after 'static'" } else { "Expected 'def'" };
+        self.consume(&TokenType::Def, message)?;
+
+        let name = if let TokenType::Identifier(name) = &self.advance().token_type {
+            name.clone()
+        } else {
+            return Err("Expected method name".to_string());
+        };
+
+        self.consume(&TokenType::LeftParen, "Expected '('")?;
+
+        let parameters = self.parameter_list()?;
+
+        self.consume(&TokenType::RightParen, "Expected ')'")?;
+        self.consume(&TokenType::Colon, "Expected ':' before return type")?;
+        let return_type = self.parse_type()?;
+
+        let braced = self.open_block()?;
+        let body = self.function_body(braced)?;
+        self.check_returns("Method", &name, &return_type, &body, span);
+
         Ok(Method {
             name,
+            is_static,
             parameters,
             return_type,
             body,
         })
     }
 
+    /// Parses the statements of a function or method body and its closing
+    /// `end`, or `}` if the body was opened with `{`.
+    fn function_body(&mut self, braced: bool) -> Result<Vec<Statement>, String> {
+        // Loops outside the definition don't extend into its body
+        let enclosing_loops = std::mem::take(&mut self.loop_depth);
+        self.function_depth += 1;
+        let body = self.block_statements(&[]);
+        self.loop_depth = enclosing_loops;
+        self.function_depth -= 1;
+
+        self.close_block(braced)?;
+        self.consume_newline_or_eof()?;
+        Ok(body)
+    }
+
+    /// Starts a block after its header: `{` opens a braced block, closed by
+    /// `}`, and anything else must be the line break before a block closed
+    /// by `end`. Returns whether the block is braced.
+    fn open_block(&mut self) -> Result<bool, String> {
+        if self.check(&TokenType::LeftBrace) {
+            self.advance();
+            Ok(true)
+        } else {
+            self.consume_newline_or_eof()?;
+            Ok(false)
+        }
+    }
+
+    /// Parses statements up to whatever closes the block, `end` or `}`, so
+    /// `close_block` can report a closer that doesn't match the opener. A
+    /// block that is followed by another part of the same statement, such as
+    /// the `catch` after a `try` body, also ends at any of `ends_at`.
+    fn block_statements(&mut self, ends_at: &[TokenType]) -> Vec<Statement> {
+        let mut statements = Vec::new();
+        while !self.at_block_close() && !ends_at.iter().any(|token| self.check(token)) && !self.is_at_end() {
+            if self.check_terminator() {
+                self.advance();
+                continue;
+            }
+            if let Some(statement) = self.statement_or_recover() {
+                statements.push(statement);
+            }
+        }
+        statements
+    }
+
+    fn at_block_close(&self) -> bool {
+        self.check(&TokenType::End) || self.check(&TokenType::RightBrace)
+    }
+
+    /// Consumes the token that closes a block: `}` for a braced block and
+    /// `end` otherwise. A block can't be opened in one style and closed in
+    /// the other.
+    fn close_block(&mut self, braced: bool) -> Result<(), String> {
+        match (braced, &self.peek().token_type) {
+            (true, TokenType::RightBrace) | (false, TokenType::End) => {
+                self.advance();
+                Ok(())
+            }
+            (true, TokenType::End) => {
+                self.advance();
+                Err("A block opened with '{' must be closed with '}', not 'end'".to_string())
+            }
+            (false, TokenType::RightBrace) => {
+                self.advance();
+                Err("A block closed with '}' must be opened with '{'".to_string())
+            }
+            (true, _) => Err("Expected '}'".to_string()),
+            (false, _) => Err("Expected 'end'".to_string()),
+        }
+    }
+
+    /// Records an error if a non-void body can run off its end without
+    /// producing a value. Parsing carries on, so later errors are still found.
+    fn check_returns(&mut self, kind: &str, name: &str, return_type: &Type, body: &[Statement], span: Span) {
+        if *return_type != Type::Void && !always_returns(body) {
+            self.errors.push(ExlineError::new(
+                format!("{} {} can reach its end without returning a value", kind, name),
+                span.line,
+                span.column,
+            ));
+        }
+    }
+
+    /// Parses a method signature, followed by a default body when the next
+    /// line is neither another `def` nor the interface's `end`.
     fn parse_interface_method(&mut self) -> Result<InterfaceMethod, String> {
+        let span = self.current_span();
         self.consume(&TokenType::Def, "Expected 'def'")?;
 
         let name = if let TokenType::Identifier(name) = &self.advance().token_type {
@@ -521,7 +1275,35 @@ impl Parser {
 
         self.consume(&TokenType::LeftParen, "Expected '('")?;
 
-        let mut parameters = Vec::new();
+        let parameters = self.parameter_list()?;
+
+        self.consume(&TokenType::RightParen, "Expected ')'")?;
+        self.consume(&TokenType::Colon, "Expected ':' before return type")?;
+        let return_type = self.parse_type()?;
+
+        self.consume_newline_or_eof()?;
+        self.skip_newlines();
+
+        let body = if self.check(&TokenType::Def) || self.check(&TokenType::End) || self.is_at_end() {
+            None
+        } else {
+            let body = self.function_body(false)?;
+            self.check_returns("Method", &name, &return_type, &body, span);
+            Some(body)
+        };
+
+        Ok(InterfaceMethod {
+            name,
+            parameters,
+            return_type,
+            body,
+        })
+    }
+
+    /// Parses `name: Type`, `name: Type = default` or `name: Type...` entries up
+    /// to the closing `)`.
+    fn parameter_list(&mut self) -> Result<Vec<Parameter>, String> {
+        let mut parameters: Vec<Parameter> = Vec::new();
         if !self.check(&TokenType::RightParen) {
             loop {
                 let param_name = if let TokenType::Identifier(name) = &self.advance().token_type {
@@ -533,84 +1315,158 @@ impl Parser {
                 self.consume(&TokenType::Colon, "Expected ':' after parameter name")?;
                 let param_type = self.parse_type()?;
 
+                if parameters.last().is_some_and(|p| p.variadic) {
+                    return Err("A variadic parameter must be the last parameter".to_string());
+                }
+
+                let variadic = self.check(&TokenType::Ellipsis);
+                if variadic {
+                    self.advance();
+                }
+
+                let default = if variadic {
+                    None
+                } else if self.check(&TokenType::Assign) {
+                    self.advance();
+                    Some(self.expression()?)
+                } else if parameters.iter().any(|p| p.default.is_some()) {
+                    return Err(format!(
+                        "Required parameter {} cannot follow a parameter with a default value",
+                        param_name
+                    ));
+                } else {
+                    None
+                };
+
                 parameters.push(Parameter {
                     name: param_name,
                     param_type,
+                    default,
+                    variadic,
                 });
 
-                if self.check(&TokenType::RightParen) {
+                if !self.separator_before_right_paren("parameters")? {
                     break;
                 }
-                // Skip commas or other separators
-                while !self.check(&TokenType::Identifier("".to_string())) && !self.check(&TokenType::RightParen) && !self.is_at_end() {
-                    if let TokenType::Identifier(_) = self.peek().token_type {
-                        break;
-                    }
-                    self.advance();
-                }
             }
         }
+        Ok(parameters)
+    }
 
-        self.consume(&TokenType::RightParen, "Expected ')'")?;
-        self.consume(&TokenType::Colon, "Expected ':' before return type")?;
-        let return_type = self.parse_type()?;
-
-        self.consume_newline_or_eof()?;
-
-        Ok(InterfaceMethod {
-            name,
-            parameters,
-            return_type,
-        })
+    /// After an item in a parenthesized list, consumes the `,` separating it
+    /// from the next. Returns whether another item follows; a trailing comma
+    /// before the `)` is allowed.
+    fn separator_before_right_paren(&mut self, items: &str) -> Result<bool, String> {
+        if self.check(&TokenType::RightParen) {
+            return Ok(false);
+        }
+        if !self.check(&TokenType::Comma) {
+            return Err(format!("Expected ',' or ')' between {}", items));
+        }
+        self.advance();
+        Ok(!self.check(&TokenType::RightParen))
     }
 
     fn parse_type(&mut self) -> Result<Type, String> {
         if self.check(&TokenType::Int) {
             self.advance();
             Ok(Type::Int)
+        } else if self.check(&TokenType::Float_) {
+            self.advance();
+            Ok(Type::Float)
         } else if self.check(&TokenType::String_) {
             self.advance();
             Ok(Type::String)
+        } else if self.check(&TokenType::Char_) {
+            self.advance();
+            Ok(Type::Char)
+        } else if self.check(&TokenType::Map) {
+            self.advance();
+            match self.type_argument()? {
+                Some(value_type) => Ok(Type::MapOf(Box::new(value_type))),
+                None => Ok(Type::Map),
+            }
+        } else if self.check(&TokenType::List) {
+            self.advance();
+            match self.type_argument()? {
+                Some(element_type) => Ok(Type::ListOf(Box::new(element_type))),
+                None => Ok(Type::List),
+            }
+        } else if self.check(&TokenType::Function) {
+            self.advance();
+            Ok(Type::Function)
         } else if self.check(&TokenType::Void) {
             self.advance();
             Ok(Type::Void)
-        } else if let TokenType::Identifier(type_name) = &self.advance().token_type {
-            Ok(Type::Custom(type_name.clone()))
+        } else if matches!(self.peek().token_type, TokenType::Identifier(_)) {
+            Ok(Type::Custom(self.qualified_name()?))
         } else {
+            self.advance();
             Err("Expected type".to_string())
         }
     }
 
-    fn expression(&mut self) -> Result<Expression, String> {
-        self.equality()
+    /// Parses a name that may be qualified by module names, as in `Geo.Point`.
+    fn qualified_name(&mut self) -> Result<String, String> {
+        let mut parts = Vec::new();
+        loop {
+            match &self.advance().token_type {
+                TokenType::Identifier(name) => parts.push(name.clone()),
+                _ => return Err("Expected name after '.'".to_string()),
+            }
+            if !self.check(&TokenType::Dot) {
+                return Ok(parts.join("."));
+            }
+            self.advance();
+        }
     }
 
-    fn equality(&mut self) -> Result<Expression, String> {
-        let mut expr = self.addition()?;
-
-        while self.check(&TokenType::Equal) {
-            self.advance();
-            let right = self.addition()?;
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator: BinaryOperator::Equal,
-                right: Box::new(right),
-            };
+    /// Parses an optional `<Type>` after `List` or `Map`.
+    fn type_argument(&mut self) -> Result<Option<Type>, String> {
+        if !self.check(&TokenType::Less) {
+            return Ok(None);
+        }
+        self.advance();
+        let argument = self.parse_type()?;
+
+        // In `List<List<Int>>` the lexer sees `>>`: take its first `>` and
+        // leave the second to close the enclosing type
+        if self.check(&TokenType::ShiftRight) {
+            let token = &mut self.tokens[self.current];
+            token.token_type = TokenType::Greater;
+            token.column += 1;
+        } else {
+            self.consume(&TokenType::Greater, "Expected '>' after type argument")?;
         }
+        Ok(Some(argument))
+    }
 
-        Ok(expr)
+    fn expression(&mut self) -> Result<Expression, String> {
+        self.binary(0)
+    }
+
+    /// Precedence climbing: parses an operand, then every following binary
+    /// operator that binds at least as tightly as `min_precedence`. How an
+    /// operator groups with its neighbours comes from
+    /// `BinaryOperator::precedence` and `BinaryOperator::associativity`.
+    fn binary(&mut self, min_precedence: u8) -> Result<Expression, String> {
+        self.nested("Expression", |parser| parser.binary_inner(min_precedence))
     }
 
-    fn addition(&mut self) -> Result<Expression, String> {
-        let mut expr = self.multiplication()?;
+    fn binary_inner(&mut self, min_precedence: u8) -> Result<Expression, String> {
+        let mut expr = self.prefix(min_precedence)?;
+
+        while let Some(operator) = binary_operator(&self.peek().token_type) {
+            let precedence = operator.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            self.advance();
 
-        while self.check(&TokenType::Plus) || self.check(&TokenType::Minus) {
-            let operator = match self.advance().token_type {
-                TokenType::Plus => BinaryOperator::Add,
-                TokenType::Minus => BinaryOperator::Subtract,
-                _ => unreachable!(),
+            let right = match operator.associativity() {
+                Associativity::Left => self.binary(precedence + 1)?,
+                Associativity::Right => self.binary(precedence)?,
             };
-            let right = self.multiplication()?;
             expr = Expression::Binary {
                 left: Box::new(expr),
                 operator,
@@ -621,52 +1477,49 @@ impl Parser {
         Ok(expr)
     }
 
-    fn multiplication(&mut self) -> Result<Expression, String> {
-        let mut expr = self.primary()?;
+    /// Parses an operand with any prefix operators. `not` binds looser than
+    /// comparisons, so `not a == b` is `not (a == b)`; it can't appear as
+    /// the operand of a tighter operator. A prefix `-` binds looser than
+    /// `**`, so `-2 ** 2` is `-(2 ** 2)`.
+    fn prefix(&mut self, min_precedence: u8) -> Result<Expression, String> {
+        if self.check(&TokenType::Not) && min_precedence <= NOT_PRECEDENCE {
+            self.advance();
+            let operand = self.binary(NOT_PRECEDENCE)?;
+            return Ok(Expression::Unary {
+                operator: UnaryOperator::Not,
+                operand: Box::new(operand),
+            });
+        }
 
-        while self.check(&TokenType::Multiply) || self.check(&TokenType::Divide) {
-            let operator = match self.advance().token_type {
-                TokenType::Multiply => BinaryOperator::Multiply,
-                TokenType::Divide => BinaryOperator::Divide,
-                _ => unreachable!(),
-            };
-            let right = self.primary()?;
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
+        if self.check(&TokenType::Minus) {
+            self.advance();
+            let operand = self.binary(BinaryOperator::Power.precedence())?;
+            return Ok(Expression::Unary {
+                operator: UnaryOperator::Negate,
+                operand: Box::new(operand),
+            });
         }
 
-        Ok(expr)
+        self.primary()
     }
 
     fn primary(&mut self) -> Result<Expression, String> {
         let token = self.advance().clone();
         let mut expr = match &token.token_type {
             TokenType::Integer(value) => Ok(Expression::Integer(*value)),
+            TokenType::Float(value) => Ok(Expression::Float(*value)),
+            TokenType::Char(value) => Ok(Expression::Char(*value)),
             TokenType::String(value) => self.parse_string_with_interpolation(value.clone()),
             TokenType::Identifier(name) => {
                 if self.check(&TokenType::LeftParen) {
                     // Function call
                     self.advance(); // consume (
-                    let mut arguments = Vec::new();
-
-                    if !self.check(&TokenType::RightParen) {
-                        loop {
-                            arguments.push(self.expression()?);
-                            if self.check(&TokenType::RightParen) {
-                                break;
-                            }
-                            // For now, we'll be lenient about comma separation
-                        }
-                    }
-
-                    self.consume(&TokenType::RightParen, "Expected ')'")?;
+                    let (arguments, keyword_arguments) = self.call_arguments()?;
 
                     Ok(Expression::FunctionCall {
                         name: name.clone(),
                         arguments,
+                        keyword_arguments,
                     })
                 } else {
                     Ok(Expression::Identifier(name.clone()))
@@ -677,63 +1530,154 @@ impl Parser {
                 self.consume(&TokenType::RightParen, "Expected ')'")?;
                 Ok(expr)
             }
+            TokenType::LeftBrace => self.map_literal(),
+            TokenType::LeftBracket => self.list_literal(),
             _ => Err("Expected expression".to_string()),
         }?;
 
-        // Handle dot notation for method calls and field access
-        while self.check(&TokenType::Dot) {
-            self.advance(); // consume dot
+        // Handle dot notation for method calls and field access, and indexing
+        while self.check(&TokenType::Dot) || self.check(&TokenType::QuestionDot) || self.check(&TokenType::LeftBracket) {
+            if self.check(&TokenType::LeftBracket) {
+                self.advance(); // consume [
+                let index = self.expression()?;
+                self.consume(&TokenType::RightBracket, "Expected ']'")?;
+                expr = Expression::Index {
+                    object: Box::new(expr),
+                    index: Box::new(index),
+                };
+                continue;
+            }
+
+            let optional = self.advance().token_type == TokenType::QuestionDot; // consume . or ?.
+
+            if let TokenType::Identifier(name) = &self.peek().token_type {
+                let field_or_method_name = name.clone();
+                self.advance(); // consume identifier
+
+                if self.check(&TokenType::LeftParen) {
+                    // Method call or object creation
+                    self.advance(); // consume (
+                    let (arguments, keyword_arguments) = self.call_arguments()?;
+
+                    // Check if this is ClassName.new() or Module.ClassName.new() - treat as object creation
+                    if field_or_method_name == "new" {
+                        if let Some(class_name) = expr.dotted_name() {
+                            expr = Expression::ObjectCreation {
+                                class_name,
+                            };
+                        } else {
+                            return Err("'new' can only be called on class names".to_string());
+                        }
+                    } else {
+                        // Regular method call
+                        expr = Expression::MethodCall {
+                            object: Box::new(expr),
+                            method: field_or_method_name,
+                            arguments,
+                            keyword_arguments,
+                            optional,
+                        };
+                    }
+                } else {
+                    // Field access
+                    expr = Expression::FieldAccess {
+                        object: Box::new(expr),
+                        field: field_or_method_name,
+                        optional,
+                    };
+                }
+            } else {
+                return Err("Expected identifier after '.'".to_string());
+            }
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses a call's arguments up to and including the closing `)`.
+    /// Positional arguments come first, then any `name: value` arguments.
+    fn call_arguments(&mut self) -> Result<(Vec<Expression>, Vec<KeywordArgument>), String> {
+        let mut arguments = Vec::new();
+        let mut keyword_arguments = Vec::new();
+
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                let is_keyword = matches!(self.peek().token_type, TokenType::Identifier(_))
+                    && self.tokens.get(self.current + 1).is_some_and(|t| t.token_type == TokenType::Colon);
+
+                if is_keyword {
+                    let name = match &self.advance().token_type {
+                        TokenType::Identifier(name) => name.clone(),
+                        _ => unreachable!(),
+                    };
+                    self.advance(); // consume :
+                    let value = self.expression()?;
+                    keyword_arguments.push(KeywordArgument { name, value });
+                } else if keyword_arguments.is_empty() {
+                    arguments.push(self.expression()?);
+                } else {
+                    return Err("Positional arguments must come before keyword arguments".to_string());
+                }
+
+                if !self.separator_before_right_paren("arguments")? {
+                    break;
+                }
+            }
+        }
+
+        self.consume(&TokenType::RightParen, "Expected ')'")?;
+        Ok((arguments, keyword_arguments))
+    }
+
+    fn map_literal(&mut self) -> Result<Expression, String> {
+        // The opening '{' has already been consumed by primary()
+        let mut entries = Vec::new();
+
+        loop {
+            self.skip_newlines();
+            if self.check(&TokenType::RightBrace) {
+                break;
+            }
+
+            let key = self.expression()?;
+            self.consume(&TokenType::Colon, "Expected ':' after map key")?;
+            let value = self.expression()?;
+            entries.push((key, value));
+
+            self.skip_newlines();
+            if self.check(&TokenType::Comma) {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+
+        self.consume(&TokenType::RightBrace, "Expected '}' after map entries")?;
+        Ok(Expression::Map(entries))
+    }
 
-            if let TokenType::Identifier(name) = &self.peek().token_type {
-                let field_or_method_name = name.clone();
-                self.advance(); // consume identifier
+    fn list_literal(&mut self) -> Result<Expression, String> {
+        // The opening '[' has already been consumed by primary()
+        let mut elements = Vec::new();
 
-                if self.check(&TokenType::LeftParen) {
-                    // Method call or object creation
-                    self.advance(); // consume (
-                    let mut arguments = Vec::new();
-
-                    if !self.check(&TokenType::RightParen) {
-                        loop {
-                            arguments.push(self.expression()?);
-                            if self.check(&TokenType::RightParen) {
-                                break;
-                            }
-                        }
-                    }
+        loop {
+            self.skip_newlines();
+            if self.check(&TokenType::RightBracket) {
+                break;
+            }
 
-                    self.consume(&TokenType::RightParen, "Expected ')'")?;
+            elements.push(self.expression()?);
 
-                    // Check if this is ClassName.new() - treat as object creation
-                    if field_or_method_name == "new" {
-                        if let Expression::Identifier(class_name) = expr {
-                            expr = Expression::ObjectCreation {
-                                class_name,
-                            };
-                        } else {
-                            return Err("'new' can only be called on class names".to_string());
-                        }
-                    } else {
-                        // Regular method call
-                        expr = Expression::MethodCall {
-                            object: Box::new(expr),
-                            method: field_or_method_name,
-                            arguments,
-                        };
-                    }
-                } else {
-                    // Field access
-                    expr = Expression::FieldAccess {
-                        object: Box::new(expr),
-                        field: field_or_method_name,
-                    };
-                }
+            self.skip_newlines();
+            if self.check(&TokenType::Comma) {
+                self.advance();
             } else {
-                return Err("Expected identifier after '.'".to_string());
+                break;
             }
         }
 
-        Ok(expr)
+        self.consume(&TokenType::RightBracket, "Expected ']' after list elements")?;
+        Ok(Expression::List(elements))
     }
 
     fn parse_string_with_interpolation(&mut self, value: String) -> Result<Expression, String> {
@@ -755,14 +1699,37 @@ impl Parser {
         }
     }
 
+    /// Statements end at a line break or a `;`.
+    fn check_terminator(&self) -> bool {
+        self.check(&TokenType::Newline) || self.check(&TokenType::Semicolon)
+    }
+
+    /// Consumes the line break or `;` ending a statement. The last
+    /// statement in a file may end at `Eof` instead, which is left for
+    /// `parse` to see.
     fn consume_newline_or_eof(&mut self) -> Result<(), String> {
-        if self.check(&TokenType::Newline) || self.check(&TokenType::Eof) {
-            if !self.is_at_end() {
-                self.advance();
-            }
-            Ok(())
-        } else {
-            Ok(()) // Be lenient for now
+        if self.check_terminator() {
+            self.advance();
+        }
+        Ok(()) // Be lenient for now
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.current > 0
+            && matches!(self.previous().token_type, TokenType::Newline | TokenType::Semicolon)
+    }
+
+    fn current_span(&self) -> Span {
+        let token = self.peek();
+        Span {
+            line: token.line,
+            column: token.column,
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.check_terminator() {
+            self.advance();
         }
     }
 
@@ -777,24 +1744,51 @@ impl Parser {
         }
     }
 
+    /// Consumes the current token and returns it. The final `Eof` is never
+    /// consumed: at the end this returns it again, rather than the token
+    /// before it, so a caller can't mistake the end for more input.
     fn advance(&mut self) -> &Token {
-        if !self.is_at_end() {
-            self.current += 1;
+        if self.is_at_end() {
+            return self.peek();
         }
+        self.current += 1;
         self.previous()
     }
 
     fn is_at_end(&self) -> bool {
-        self.current >= self.tokens.len() || self.peek().token_type == TokenType::Eof
+        self.peek().token_type == TokenType::Eof
     }
 
+    /// The current token. `new` makes sure the tokens end with `Eof`, and
+    /// `advance` never moves past it, so this is always in bounds.
     fn peek(&self) -> &Token {
         &self.tokens[self.current]
     }
 
     fn previous(&self) -> &Token {
-        &self.tokens[self.current - 1]
+        &self.tokens[self.current.saturating_sub(1)]
+    }
+}
+
+/// The program as JSON with every span blanked out, for tests that compare
+/// programs which only differ in layout.
+#[cfg(test)]
+pub(crate) fn without_spans(program: &Program) -> serde_json::Value {
+    fn blank(value: &mut serde_json::Value) {
+        let is_span = |map: &serde_json::Map<String, serde_json::Value>| {
+            map.len() == 2 && map.contains_key("line") && map.contains_key("column")
+        };
+        match value {
+            serde_json::Value::Object(map) if is_span(map) => *value = serde_json::Value::Null,
+            serde_json::Value::Object(map) => map.values_mut().for_each(blank),
+            serde_json::Value::Array(items) => items.iter_mut().for_each(blank),
+            _ => {}
+        }
     }
+
+    let mut value = serde_json::to_value(program).expect("an AST always serializes");
+    blank(&mut value);
+    value
 }
 
 #[cfg(test)]
@@ -802,6 +1796,126 @@ mod tests {
     use super::*;
     use crate::lexer::Lexer;
 
+    /// Parses source that may not lex, returning nothing either way: the
+    /// point is only that it returns.
+    fn parse_anything(source: &str) {
+        if let Ok(tokens) = Lexer::new(source).tokenize() {
+            let _ = Parser::new(tokens).parse();
+        }
+    }
+
+    #[test]
+    fn test_malformed_input_never_panics() {
+        let program = r#"
+module Geo
+  interface Shape
+    def area() : Float
+  end
+  class Square implements Shape
+    Float side = 2.0
+    static def unit() : Square
+      return Square.new()
+    end
+    def area() : Float {
+      this.side ** 2
+    }
+  end
+end
+const Int LIMIT = 3
+def count(xs: List<Int>, by: Int = 1, rest: Int...) -> Map<List<Int>>
+  Map<List<Int>> seen = {"a": [1, 2,], "b": []}
+  Int i = 0
+  while i < LIMIT and not (i == 2)
+    if i % 2 == 0 { i = i + by } elif i > 5 { break } else { continue }
+    repeat
+      i = i + 1
+    until i >= 1
+  end
+  match xs[0]
+    case 1
+      print("one #{i}")
+    default
+      raise "other"
+  end
+  try
+    do
+      Function f = count
+      f(xs, by: 2)?.size
+    end
+  catch e
+    eprintln(e); exit(1)
+  end
+  return seen
+end
+Geo.Square.new().area()
+"#;
+        // Every truncation of a program that uses most of the syntax, so blocks,
+        // calls and expressions are cut off at every possible point
+        let chars: Vec<char> = program.chars().collect();
+        for end in 0..=chars.len() {
+            parse_anything(&chars[..end].iter().collect::<String>());
+        }
+
+        for source in [
+            "", " ", "\n\n", "+", "-", "*", "**", "(", ")", "[", "]", "{", "}", ",", ".", "?.", "...", ":", ";", "->",
+            "=", "==", "<", ">", "<<", ">>", "not", "and", "end", "else", "elif", "until", "case", "default", "catch",
+            "def", "def f", "def f(", "def f(x", "def f(x:", "def f() ->", "class", "class A implements", "interface",
+            "module", "if", "while", "repeat", "match", "try", "do", "raise", "return", "const", "const Int", "static",
+            "Int", "Int x", "Int x =", "List<", "List<Int", "Map<List<", "x.", "x?.", "x[", "x[1", "f(", "f(a:", "f(a: 1,",
+            "A.new(", "1 +", "1 + * 2", "((((", "))))", "[1, 2", "{\"a\"", "{\"a\":", "break", "continue",
+        ] {
+            parse_anything(source);
+        }
+
+        // Nesting far past the limit is an error rather than a stack overflow
+        let parens = format!("print({}1{})", "(".repeat(50_000), ")".repeat(50_000));
+        let minuses = format!("print({}1)", "-".repeat(200_000));
+        let lists = format!("print({}1{})", "[".repeat(50_000), "]".repeat(50_000));
+        let blocks = format!("{}print(1)\n{}", "do\n".repeat(50_000), "end\n".repeat(50_000));
+        for (source, message) in [
+            (parens, "Expression nested too deeply"),
+            (minuses, "Expression nested too deeply"),
+            (lists, "Expression nested too deeply"),
+            (blocks, "Blocks nested too deeply"),
+        ] {
+            let tokens = Lexer::new(&source).tokenize().unwrap();
+            let errors = Parser::new(tokens).parse().unwrap_err();
+            assert_eq!(errors[0].message, message);
+        }
+
+        // Nesting up to the limit still parses
+        let source = format!("print({}1{})", "(".repeat(100), ")".repeat(100));
+        let tokens = Lexer::new(&source).tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_ok());
+    }
+
+    #[test]
+    fn test_empty_and_blank_sources() {
+        for source in ["", "\n\n\n", "   \t\r\n  \n", "# just a comment", "# one\n  # two\n\n", "#!/usr/bin/env exline\n", ";\n;;"] {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            let program = Parser::new(tokens).parse().unwrap();
+            assert!(program.statements.is_empty(), "{:?}", source);
+        }
+
+        // A statement at the very end of the file needs no line break after it
+        let tokens = Lexer::new("\n\nprint(1) # done").tokenize().unwrap();
+        assert_eq!(Parser::new(tokens).parse().unwrap().statements.len(), 1);
+    }
+
+    #[test]
+    fn test_parser_handles_token_lists_without_eof() {
+        assert_eq!(Parser::new(Vec::new()).parse().unwrap().statements.len(), 0);
+
+        let mut tokens = Lexer::new("Int x = 1 +").tokenize().unwrap();
+        tokens.pop(); // drop the Eof
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(errors[0].message, "Expected expression");
+
+        let mut tokens = Lexer::new("print(1)").tokenize().unwrap();
+        tokens.pop();
+        assert_eq!(Parser::new(tokens).parse().unwrap().statements.len(), 1);
+    }
+
     #[test]
     fn test_variable_declaration() {
         let mut lexer = Lexer::new("Int n1 = 1");
@@ -826,10 +1940,491 @@ mod tests {
         let program = parser.parse().unwrap();
 
         assert_eq!(program.statements.len(), 1);
-        if let Statement::Expression(Expression::Binary { operator, .. }) = &program.statements[0] {
+        if let Statement::Expression(Expression::Binary { operator, .. }, _) = &program.statements[0] {
             assert_eq!(*operator, BinaryOperator::Add);
         } else {
             panic!("Expected binary expression");
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_method_call_on_function_result() {
+        let mut lexer = Lexer::new("makePerson().greet()");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        assert_eq!(program.statements.len(), 1);
+        if let Statement::Expression(Expression::MethodCall { object, method, arguments, .. }, _) = &program.statements[0] {
+            assert_eq!(method, "greet");
+            assert!(arguments.is_empty());
+            assert!(matches!(&**object, Expression::FunctionCall { name, arguments, .. } if name == "makePerson" && arguments.is_empty()));
+        } else {
+            panic!("Expected method call");
+        }
+    }
+
+    #[test]
+    fn test_typed_collection_types() {
+        let mut lexer = Lexer::new("List<Int> xs = []\nMap<List<List<String>>> m = {}\nList<Int ys = []");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let errors = parser.parse().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Expected '>' after type argument");
+        assert_eq!(errors[0].line, 3);
+
+        let mut lexer = Lexer::new("List<Int> xs = []\nMap<List<List<String>>> m = {}");
+        let program = Parser::new(lexer.tokenize().unwrap()).parse().unwrap();
+        let types: Vec<&Type> = program
+            .statements
+            .iter()
+            .map(|statement| match statement {
+                Statement::VariableDeclaration { var_type, .. } => var_type,
+                _ => panic!("Expected declaration"),
+            })
+            .collect();
+
+        assert_eq!(*types[0], Type::ListOf(Box::new(Type::Int)));
+        assert_eq!(
+            *types[1],
+            Type::MapOf(Box::new(Type::ListOf(Box::new(Type::ListOf(Box::new(Type::String))))))
+        );
+    }
+
+    #[test]
+    fn test_function_type() {
+        let mut lexer = Lexer::new("def apply(f: Function, n: Int) -> Int\n  return f(n)\nend\nFunction g = apply");
+        let program = Parser::new(lexer.tokenize().unwrap()).parse().unwrap();
+
+        match &program.statements[0] {
+            Statement::FunctionDefinition { parameters, .. } => assert_eq!(parameters[0].param_type, Type::Function),
+            other => panic!("Expected function definition, got {:?}", other),
+        }
+        match &program.statements[1] {
+            Statement::VariableDeclaration { var_type, value, .. } => {
+                assert_eq!(*var_type, Type::Function);
+                assert_eq!(*value, Expression::Identifier("apply".to_string()));
+            }
+            other => panic!("Expected declaration, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_map_literal_and_index() {
+        let mut lexer = Lexer::new("Map m = { \"a\": 1, \"b\": 2 }\nm[\"a\"] = 3");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        assert_eq!(program.statements.len(), 2);
+        if let Statement::VariableDeclaration { var_type, value: Expression::Map(entries), .. } = &program.statements[0] {
+            assert_eq!(*var_type, Type::Map);
+            assert_eq!(entries.len(), 2);
+        } else {
+            panic!("Expected map declaration");
+        }
+        if let Statement::Assignment { target: Expression::Index { .. }, .. } = &program.statements[1] {
+        } else {
+            panic!("Expected index assignment");
+        }
+    }
+
+    #[test]
+    fn test_custom_type_declaration() {
+        let mut lexer = Lexer::new("Person p = Person.new()");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        assert_eq!(program.statements.len(), 1);
+        if let Statement::VariableDeclaration { name, var_type, .. } = &program.statements[0] {
+            assert_eq!(name, "p");
+            assert_eq!(*var_type, Type::Custom("Person".to_string()));
+        } else {
+            panic!("Expected variable declaration");
+        }
+    }
+
+    #[test]
+    fn test_statement_spans() {
+        let mut lexer = Lexer::new("Int a = 1\n\n  print(a)");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        if let Statement::Expression(_, span) = &program.statements[1] {
+            assert_eq!((span.line, span.column), (3, 3));
+        } else {
+            panic!("Expected expression statement");
+        }
+    }
+
+    #[test]
+    fn test_collects_errors_and_recovers_inside_blocks() {
+        let source = "def f() -> Int\n  Int x = )\n  1\nend\nInt y = \nprint(y)";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let errors = parser.parse().unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].line, 2);
+        assert_eq!(errors[1].line, 5);
+    }
+
+    #[test]
+    fn test_default_parameter_values() {
+        let mut lexer = Lexer::new("def greet(name: String, greeting: String = \"Hello\") -> String\n  greeting\nend");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        if let Statement::FunctionDefinition { parameters, .. } = &program.statements[0] {
+            assert_eq!(parameters[0].default, None);
+            assert_eq!(parameters[1].default, Some(Expression::String("Hello".to_string())));
+        } else {
+            panic!("Expected function definition");
+        }
+
+        let mut lexer = Lexer::new("def f(a: Int = 1, b: Int) -> Int\n  a\nend");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let errors = parser.parse().unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "Required parameter b cannot follow a parameter with a default value"
+        );
+    }
+
+    /// Parses a single expression statement and renders it fully
+    /// parenthesized, so tests can assert how it was grouped.
+    fn grouping(source: &str) -> String {
+        fn render(expr: &Expression) -> String {
+            match expr {
+                Expression::Binary { left, operator, right } => {
+                    format!("({} {:?} {})", render(left), operator, render(right))
+                }
+                Expression::Unary { operator, operand } => format!("({:?} {})", operator, render(operand)),
+                Expression::Identifier(name) => name.clone(),
+                Expression::Integer(n) => n.to_string(),
+                other => panic!("Unexpected expression {:?}", other),
+            }
+        }
+
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        match &Parser::new(tokens).parse().unwrap().statements[0] {
+            Statement::Expression(expr, _) => render(expr),
+            other => panic!("Expected an expression, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_precedence_ladder() {
+        assert_eq!(grouping("a + b == c * d"), "((a Add b) Equal (c Multiply d))");
+        assert_eq!(grouping("not a and b"), "((Not a) And b)");
+        assert_eq!(grouping("a or b and c"), "(a Or (b And c))");
+        assert_eq!(grouping("not a == b"), "(Not (a Equal b))");
+        assert_eq!(grouping("a < b == c > d"), "((a Less b) Equal (c Greater d))");
+        assert_eq!(grouping("a != b or c <= d | e"), "((a NotEqual b) Or (c LessEqual (d BitOr e)))");
+        assert_eq!(grouping("-a ** 2 * b + c"), "(((Negate (a Power 2)) Multiply b) Add c)");
+        assert_eq!(grouping("not not a or b"), "((Not (Not a)) Or b)");
+    }
+
+    #[test]
+    fn test_associativity() {
+        assert_eq!(grouping("a - b - c"), "((a Subtract b) Subtract c)");
+        assert_eq!(grouping("a // b * c % d"), "(((a IntDivide b) Multiply c) Modulo d)");
+        assert_eq!(grouping("a ** b ** c"), "(a Power (b Power c))");
+        assert_eq!(grouping("a ** -b ** c"), "(a Power (Negate (b Power c)))");
+        assert_eq!(grouping("a or b or c"), "((a Or b) Or c)");
+
+        assert_eq!(BinaryOperator::Subtract.associativity(), Associativity::Left);
+        assert_eq!(BinaryOperator::Power.associativity(), Associativity::Right);
+    }
+
+    #[test]
+    fn test_keyword_arguments() {
+        let mut lexer = Lexer::new("greet(\"Bob\", greeting: \"Hi\")\np.greet(greeting: \"Hi\")\ngreet(greeting: \"Hi\", \"Bob\")");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let errors = parser.parse().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Positional arguments must come before keyword arguments");
+        assert_eq!(errors[0].line, 3);
+
+        let mut lexer = Lexer::new("greet(\"Bob\", greeting: \"Hi\")");
+        let program = Parser::new(lexer.tokenize().unwrap()).parse().unwrap();
+        assert_eq!(
+            program.statements[0],
+            Statement::Expression(
+                Expression::FunctionCall {
+                    name: "greet".to_string(),
+                    arguments: vec![Expression::String("Bob".to_string())],
+                    keyword_arguments: vec![KeywordArgument {
+                        name: "greeting".to_string(),
+                        value: Expression::String("Hi".to_string()),
+                    }],
+                },
+                Span { line: 1, column: 1 }
+            )
+        );
+    }
+
+    #[test]
+    fn test_variadic_parameter_must_be_last() {
+        let mut lexer = Lexer::new("def f(nums: Int..., label: String) -> Int\n  0\nend");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let errors = parser.parse().unwrap_err();
+        assert_eq!(errors[0].message, "A variadic parameter must be the last parameter");
+    }
+
+    #[test]
+    fn test_break_outside_loop_is_an_error() {
+        let source = "break\nwhile 1\n  def f() -> void\n    continue\n  end\n  break\nend";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let errors = parser.parse().unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].message.as_str(), errors[0].line), ("'break' outside of a loop", 1));
+        assert_eq!((errors[1].message.as_str(), errors[1].line), ("'continue' outside of a loop", 4));
+    }
+
+    #[test]
+    fn test_empty_bodies() {
+        let source = "def f() -> void\nend\nclass A\nend\ninterface B\nend\nif 1\nelse\nend";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        assert_eq!(program.statements.len(), 4);
+        match &program.statements[3] {
+            Statement::If { then_branch, else_branch, .. } => {
+                assert!(then_branch.is_empty());
+                assert_eq!(else_branch.as_deref(), Some(&[][..]));
+            }
+            other => panic!("Expected if statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_semicolons_separate_statements() {
+        let source = "Int a = 1; Int b = 2;\nwhile a < b; a = a + 1; end;;";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        assert_eq!(program.statements.len(), 3);
+        match &program.statements[2] {
+            Statement::While { body, .. } => assert_eq!(body.len(), 1),
+            other => panic!("Expected while statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_repeat_until() {
+        let mut lexer = Lexer::new("repeat\n  x = x + 1\n  break\nuntil x > 3");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        match &program.statements[0] {
+            Statement::RepeatUntil { body, condition, .. } => {
+                assert_eq!(body.len(), 2);
+                assert!(matches!(condition, Expression::Binary { operator: BinaryOperator::Greater, .. }));
+            }
+            other => panic!("Expected repeat statement, got {:?}", other),
+        }
+
+        let mut lexer = Lexer::new("repeat\n  x = 1\nend");
+        let tokens = lexer.tokenize().unwrap();
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(errors[0].message, "Expected 'until' to close 'repeat'");
+    }
+
+    #[test]
+    fn test_elif_chain() {
+        let source = "if x == 1\n  print(1)\nelif x == 2\n  print(2)\nelif x == 3\nelse\n  print(0)\nend\nprint(x)";
+        let mut lexer = Lexer::new(source);
+        let program = Parser::new(lexer.tokenize().unwrap()).parse().unwrap();
+        assert_eq!(program.statements.len(), 2);
+
+        // Each `elif` is an `if` nested alone in the else branch of the one before
+        let Statement::If { then_branch, else_branch: Some(else_branch), .. } = &program.statements[0] else {
+            panic!("Expected if statement, got {:?}", program.statements[0]);
+        };
+        assert_eq!(then_branch.len(), 1);
+        let [Statement::If { span, then_branch, else_branch: Some(else_branch), .. }] = else_branch.as_slice() else {
+            panic!("Expected elif, got {:?}", else_branch);
+        };
+        assert_eq!((span.line, span.column, then_branch.len()), (3, 1, 1));
+        let [Statement::If { then_branch, else_branch: Some(else_branch), .. }] = else_branch.as_slice() else {
+            panic!("Expected elif, got {:?}", else_branch);
+        };
+        assert!(then_branch.is_empty());
+        assert!(matches!(else_branch.as_slice(), [Statement::Expression(..)]));
+    }
+
+    #[test]
+    fn test_elif_errors() {
+        // One `end` closes the whole chain, so a second is left over
+        for (source, message, line) in [
+            ("if x\nelif y\nend\nend", "Expected expression", 4),
+            ("if x\nelse\nelif y\nend", "'elif' must come before 'else'", 3),
+            ("if x\nelif\nend", "Expected expression", 2),
+        ] {
+            let mut lexer = Lexer::new(source);
+            let errors = Parser::new(lexer.tokenize().unwrap()).parse().unwrap_err();
+            assert_eq!((errors[0].message.as_str(), errors[0].line), (message, line), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_brace_blocks() {
+        let braced = r#"class Counter implements Countable {
+  Int count = 0
+  def bump(by: Int) : Int {
+    this.count = this.count + by
+    return this.count
+  }
+}
+def sign(n: Int) -> Int {
+  if n < 0 { return -1 } elif n == 0 {
+    return 0
+  } else { return 1 }
+}
+while x { x = x - 1 }
+def noop() -> void {}"#;
+        let with_end = r#"class Counter implements Countable
+  Int count = 0
+  def bump(by: Int) : Int
+    this.count = this.count + by
+    return this.count
+  end
+end
+def sign(n: Int) -> Int
+  if n < 0
+    return -1
+  elif n == 0
+    return 0
+  else
+    return 1
+  end
+end
+while x
+  x = x - 1
+end
+def noop() -> void
+end"#;
+        let parse = |source: &str| Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+        assert_eq!(without_spans(&parse(braced)), without_spans(&parse(with_end)));
+    }
+
+    #[test]
+    fn test_mixed_block_styles() {
+        for (source, message, line) in [
+            ("def f() -> void {\n  print(1)\nend", "A block opened with '{' must be closed with '}', not 'end'", 3),
+            ("while x\n  x = 0\n}", "A block closed with '}' must be opened with '{'", 3),
+            ("class A {\n  Int n\nend", "A block opened with '{' must be closed with '}', not 'end'", 3),
+            (
+                "if x {\n  print(1)\n} else\n  print(2)\nend",
+                "Every branch of an if must use '{' if the first one does, and none otherwise",
+                3,
+            ),
+            (
+                "if x\n  print(1)\nelif y {\n  print(2)\n}",
+                "Every branch of an if must use '{' if the first one does, and none otherwise",
+                3,
+            ),
+            ("def f() -> void {\n  print(1)\n", "Expected '}'", 2),
+            // Keyword-only blocks report a stray '}' the same way
+            ("do\n  x = 0\n}", "A block closed with '}' must be opened with '{'", 3),
+            ("try\n  x = 0\ncatch e\n  x = 1\n}", "A block closed with '}' must be opened with '{'", 5),
+        ] {
+            let errors = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap_err();
+            assert_eq!((errors[0].message.as_str(), errors[0].line), (message, line), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_module_definition() {
+        let source = "module Geo\n  class Point\n  end\nend\nGeo.Point p = Geo.Point.new()\nmodule Bad\n  Int x = 1\nend";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            (errors[0].message.as_str(), errors[0].line),
+            ("Only classes, interfaces, functions and modules can be defined in a module", 7)
+        );
+
+        let mut lexer = Lexer::new(source.split("\nmodule Bad").next().unwrap());
+        let tokens = lexer.tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert!(matches!(&program.statements[0], Statement::Module { name, body, .. } if name == "Geo" && body.len() == 1));
+        match &program.statements[1] {
+            Statement::VariableDeclaration { var_type, value, .. } => {
+                assert_eq!(var_type, &Type::Custom("Geo.Point".to_string()));
+                assert_eq!(value, &Expression::ObjectCreation { class_name: "Geo.Point".to_string() });
+            }
+            other => panic!("Expected declaration, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_trailing_commas() {
+        let parse = |source: &str| Parser::new(Lexer::new(source).tokenize().unwrap()).parse();
+
+        let with_commas = parse("def f(a: Int, b: Int,) -> Int\n  a\nend\nf(1, b: 2,)\nx.m(1,)").unwrap();
+        let without = parse("def f(a: Int, b: Int) -> Int\n  a\nend\nf(1, b: 2)\nx.m(1)").unwrap();
+        assert_eq!(with_commas, without);
+
+        assert_eq!(parse("f(1 2)").unwrap_err()[0].message, "Expected ',' or ')' between arguments");
+        assert_eq!(parse("f(1,,)").unwrap_err()[0].message, "Expected expression");
+        assert_eq!(
+            parse("def f(a: Int b: Int) -> void\nend").unwrap_err()[0].message,
+            "Expected ',' or ')' between parameters"
+        );
+    }
+
+    #[test]
+    fn test_implements_several_interfaces() {
+        let parse = |source: &str| Parser::new(Lexer::new(source).tokenize().unwrap()).parse();
+
+        let program = parse("class Duck implements Walker, Geo.Swimmer\nend").unwrap();
+        match &program.statements[0] {
+            Statement::ClassDefinition { implements, .. } => assert_eq!(implements, &["Walker", "Geo.Swimmer"]),
+            other => panic!("Expected class definition, got {:?}", other),
+        }
+
+        let errors = parse("class Duck implements Walker,\nend").unwrap_err();
+        assert_eq!(errors[0].message, "Expected interface name after 'implements'");
+    }
+
+    #[test]
+    fn test_interface_default_method() {
+        let source = "interface Named\n  def name() : String\n\n  def greet() : String\n    \"Hi \" + this.name()\n  end\n  def wave() : void\nend";
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+        match &program.statements[0] {
+            Statement::InterfaceDefinition { methods, .. } => {
+                let bodies: Vec<Option<usize>> = methods.iter().map(|m| m.body.as_ref().map(Vec::len)).collect();
+                assert_eq!(bodies, vec![None, Some(1), None]);
+            }
+            other => panic!("Expected interface definition, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_return_outside_function_is_an_error() {
+        let mut lexer = Lexer::new("return 1");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let errors = parser.parse().unwrap_err();
+        assert_eq!(errors[0].message, "'return' outside of a function");
+    }
+}